@@ -1,14 +1,42 @@
-/// Handling of commands, arguments.
-/// Also interacts with config module to
-/// gather/store configuration.
+//! Handling of commands, arguments.
+//! Also interacts with config module to
+//! gather/store configuration.
 
-use std::{env, path::PathBuf, process::Command};
+use std::{
+    env, fs, mem,
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    time::Duration,
+};
 
 use anyhow::{bail, Context};
-use clap::{arg, command, value_parser, ArgMatches, Command as CCommand};
+use clap::{arg, command, value_parser, ArgAction, ArgMatches, Command as CCommand};
 
 use crate::{
-    subcommand, tool::{config::Config, creator::Project, executor, initiator}, util::{files::FileHandler, MISSING_CFG}
+    subcommand,
+    tool::{
+        analyzer,
+        bench::{self, BenchOpts},
+        bundler,
+        cleaner::{Cleaner, TargetKind},
+        config::Config,
+        creator::Project,
+        deps::Update,
+        documenter,
+        editor::{self, AddOpts},
+        executor::{self, Target},
+        exporter::{self, ExportFormat},
+        formatter, graph,
+        importer::ImportFormat,
+        initiator,
+        installer::{self, DEFAULT_PREFIX},
+        watcher::{WatchAction, Watcher},
+        workspace::{Workspace, WorkspaceManifest, WORKSPACE_FILE},
+    },
+    util::{
+        self, files::FileHandler, log, CONFIG_FILE, DEFAULT_TARGET_DIR, LOCKFILE, LOCKFILE_NAME,
+        MISSING_CFG, MISSING_WORKSPACE, TARGET_DIR,
+    },
 };
 
 const INTRO: &str = r#"
@@ -18,33 +46,163 @@ The most important commands are:
 - new <name> // create a new surtur C project
 - run // compiles and executes your program
 - build // compiles your program
-- add <name> // adds the specified library
-- remove <name> // removes the specified library
+- run --bin <name> // compiles and executes one of your binaries
+- add <dependency> // adds a dependency to your project.lua
+- remove <name> // removes a dependency from your project.lua
 - update // Update & install dependencies
+- bench // builds and runs your benchmarks
+- check // checks your program for errors without building it
+- analyze // runs the static analyzer over your program
+- fmt // formats your code with clang-format
+- doc // generates documentation from your headers
+- install // installs your library
+- bundle // packs your program into a distributable archive
+- export // exports your build as Makefile, CMake or Ninja files
+- clean // removes build outputs
+- watch // rebuilds your program on changes
 - init // initialize a surtur C project
 "#;
 
 pub struct Cli {
     pub cfg: Option<Config>,
+    /// Workspace the project belongs to, its members are loaded by `workspace()`
+    pub workspace: Option<WorkspaceManifest>,
+    /// Features enabled with `--features`
+    features: Vec<String>,
+    /// Root directory of the project or workspace
     pub cur_dir: PathBuf,
+    matches: ArgMatches,
 }
 
 impl Cli {
     pub fn new() -> anyhow::Result<Self> {
-        let cur_dir = env::current_dir()?;
-
-        let path = cur_dir.join("project.lua");
+        let matches = Self::handle_cmd();
 
-        let fh = FileHandler::new(path.as_path())
-            .context(format!("Failed to find path: {path:?}"))
-            .ok();
-        let cfg = if let Some(fh) = fh {
-            Some(Config::parse(&cur_dir, fh)?)
+        let verbosity = if matches.get_flag("quiet") {
+            -1
         } else {
-            None
+            matches.get_count("verbose").min(2) as i8
+        };
+        let build_plan = matches.get_flag("build-plan");
+        log::init(verbosity, matches.get_flag("dry-run") || build_plan);
+        graph::init(matches.get_flag("keep-going"), build_plan);
+
+        if let Some(dir) = matches.get_one::<PathBuf>("directory") {
+            env::set_current_dir(dir)
+                .context(format!("Failed to change directory to {}", dir.display()))?;
+        }
+
+        let cwd = env::current_dir()?;
+
+        // New projects don't belong to the project or workspace around them
+        let creates = matches!(matches.subcommand_name(), Some("new" | "init"));
+        let manifest = match matches.get_one::<PathBuf>("manifest-path") {
+            _ if creates => None,
+            Some(path) => Some(cwd.join(path)),
+            None => Self::find_manifest(&cwd),
+        };
+
+        let mut cfg = None;
+        let mut workspace = None;
+        let mut cur_dir = cwd.clone();
+
+        if let Some(manifest) = manifest {
+            cur_dir = manifest
+                .parent()
+                .context(format!("Invalid manifest path: {}", manifest.display()))?
+                .to_path_buf();
+            log::debug(format!("Using manifest {}", manifest.display()));
+            let fh = FileHandler::new(&manifest)
+                .context(format!("Failed to read manifest: {}", manifest.display()))?;
+            if fh.file_name.as_deref() == Some(WORKSPACE_FILE) {
+                workspace = Some(WorkspaceManifest::parse(&cur_dir, fh)?);
+            } else {
+                cfg = Some(Config::parse(&cur_dir, fh)?);
+                workspace = Self::find_workspace(&cur_dir)?;
+            }
+        }
+
+        let features: Vec<String> = matches
+            .get_many::<String>("features")
+            .into_iter()
+            .flatten()
+            .flat_map(|list| list.split(','))
+            .filter(|feature| !feature.is_empty())
+            .map(String::from)
+            .collect();
+        if let Some(cfg) = &mut cfg {
+            cfg.features.extend(features.iter().cloned());
+        }
+
+        let target_dir = match (env::var_os(TARGET_DIR), &cfg, &workspace) {
+            (Some(dir), _, _) => cwd.join(dir),
+            // Workspace members share the workspace's target directory
+            (None, _, Some(ws)) => ws.target_dir.clone(),
+            (None, Some(cfg), None) => cfg.target_dir.clone(),
+            (None, None, None) => cur_dir.join(DEFAULT_TARGET_DIR),
+        };
+        log::debug(format!("Using target directory {}", target_dir.display()));
+        env::set_var(TARGET_DIR, target_dir);
+
+        // Workspace members share the workspace's lockfile
+        let lockfile = match &workspace {
+            Some(ws) => ws.root_dir.join(LOCKFILE_NAME),
+            None => cur_dir.join(LOCKFILE_NAME),
         };
+        env::set_var(LOCKFILE, lockfile);
+
+        Ok(Self {
+            cfg,
+            workspace,
+            features,
+            cur_dir,
+            matches,
+        })
+    }
+
+    /// Searches the directory and all of its parents for a
+    /// project.lua or workspace.lua file
+    fn find_manifest(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors().find_map(|dir| {
+            [CONFIG_FILE, WORKSPACE_FILE]
+                .iter()
+                .map(|file| dir.join(file))
+                .find(|path| path.is_file())
+        })
+    }
+
+    /// Finds the workspace the project is a member of
+    fn find_workspace(proj_dir: &Path) -> anyhow::Result<Option<WorkspaceManifest>> {
+        let proj_dir = proj_dir.canonicalize()?;
+        for dir in proj_dir.ancestors() {
+            let ws_path = dir.join(WORKSPACE_FILE);
+            if let Ok(fh) = FileHandler::new(&ws_path) {
+                let ws = WorkspaceManifest::parse(dir, fh)?;
+                return Ok(if dir == proj_dir || ws.has_member(&proj_dir) {
+                    Some(ws)
+                } else {
+                    None
+                });
+            }
+        }
+        Ok(None)
+    }
 
-        Ok(Self { cfg, cur_dir })
+    /// Loads the members of the workspace, only commands that work on them need it
+    fn workspace(&self) -> anyhow::Result<Option<Workspace>> {
+        self.workspace
+            .as_ref()
+            .map(|ws| ws.load(&self.features))
+            .transpose()
+    }
+
+    /// Loads the workspace if the command works on its members, which is
+    /// the case with `--package` or outside of a member's directory
+    fn members_for(&self, package: Option<&String>) -> anyhow::Result<Option<Workspace>> {
+        if package.is_none() && self.cfg.is_some() {
+            return Ok(None);
+        }
+        self.workspace()
     }
 
     #[inline]
@@ -52,33 +210,106 @@ impl Cli {
         self.match_args()
     }
 
-    fn match_args(self) -> anyhow::Result<()> {
-        match Self::handle_cmd() {
+    fn match_args(mut self) -> anyhow::Result<()> {
+        let matches = mem::take(&mut self.matches);
+
+        match matches {
             m if m.subcommand_matches("run").is_some() => {
                 let matches = m.subcommand_matches("run").unwrap();
 
                 let args: Option<Vec<&String>> =
                     matches.get_many("PROGRAM_ARGS").map(|many| many.collect());
 
-                executor::run_c(self, true, args)?
+                let target = match (
+                    matches.get_one::<String>("bin"),
+                    matches.get_one::<String>("example"),
+                ) {
+                    (Some(name), _) => Target::Bin(name),
+                    (None, Some(name)) => Target::Example(name),
+                    (None, None) => Target::Main,
+                };
+
+                match matches.get_one::<String>("package") {
+                    Some(name) => {
+                        let member = self
+                            .workspace()?
+                            .context(MISSING_WORKSPACE)?
+                            .into_member(name)?;
+                        executor::run_proj(&member.dir, member.cfg, target, true, args)?
+                    }
+                    None => executor::run_c(self, target, true, args)?,
+                }
             }
             m if m.subcommand_matches("build").is_some() => {
                 let matches = m.subcommand_matches("build").unwrap();
 
                 let enable_dbg = matches.get_flag("debug");
                 let is_release = matches.get_flag("release");
+                let target = if matches.get_flag("bins") {
+                    Target::Bins
+                } else if matches.get_flag("examples") {
+                    Target::Examples
+                } else {
+                    Target::Main
+                };
 
-                executor::build_c(self, enable_dbg, false, is_release)
-                    .context("Failed to build program as executable")?;
+                match (
+                    self.members_for(matches.get_one::<String>("package"))?,
+                    matches.get_one::<String>("package"),
+                ) {
+                    (Some(ws), Some(name)) => {
+                        let mut member = ws.into_member(name)?;
+                        executor::build_proj(
+                            &member.dir,
+                            &mut member.cfg,
+                            target,
+                            enable_dbg,
+                            false,
+                            is_release,
+                        )
+                        .context("Failed to build program as executable")?;
+                    }
+                    (Some(ws), None) if self.cfg.is_none() => {
+                        executor::build_workspace(ws, target, enable_dbg, is_release)?;
+                    }
+                    (None, Some(_)) => bail!(MISSING_WORKSPACE),
+                    _ => {
+                        let mut cfg = self.cfg.context(MISSING_CFG)?;
+                        executor::build_proj(
+                            &self.cur_dir,
+                            &mut cfg,
+                            target,
+                            enable_dbg,
+                            false,
+                            is_release,
+                        )
+                        .context("Failed to build program as executable")?;
+                    }
+                }
             }
             m if m.subcommand_matches("init").is_some() => {
-                initiator::init_proj(&Project::new(&self.cur_dir))?;
+                let cmd = m.subcommand_matches("init").unwrap();
+                // Unwrap is safe because of the possible values
+                let from = cmd
+                    .get_one::<String>("from")
+                    .map(|from| ImportFormat::from_str(from).unwrap());
+                initiator::init_proj(&Project::new(&env::current_dir()?), from)?;
             }
             m if m.subcommand_matches("test").is_some() => self.run_test(m)?,
-            m if m.subcommand_matches("update").is_some() => {
-                self.update(m.subcommand_matches("update").unwrap().get_flag("force"))?
-            }
+            m if m.subcommand_matches("update").is_some() => self.update(m)?,
+            m if m.subcommand_matches("add").is_some() => self.add(m)?,
+            m if m.subcommand_matches("remove").is_some() => self.remove(m)?,
             m if m.subcommand_matches("mem-check").is_some() => self.check_mem()?,
+            m if m.subcommand_matches("bench").is_some() => self.bench(m)?,
+            m if m.subcommand_matches("check").is_some() => self.check(m)?,
+            m if m.subcommand_matches("analyze").is_some() => self.analyze(m)?,
+            m if m.subcommand_matches("fmt").is_some() => self.fmt(m)?,
+            m if m.subcommand_matches("doc").is_some() => self.doc(m)?,
+            m if m.subcommand_matches("install").is_some() => self.install(m)?,
+            m if m.subcommand_matches("bundle").is_some() => self.bundle()?,
+            m if m.subcommand_matches("export").is_some() => self.export(m)?,
+            m if m.subcommand_matches("clean").is_some() => self.clean(m)?,
+            m if m.subcommand_matches("watch").is_some() => self.watch(m)?,
             // Switch this to if let guards once they are stabelized
             m if m.subcommand_matches("new").is_some() => Self::new_proj(m)?,
             _ => println!("{}", INTRO),
@@ -88,6 +319,46 @@ impl Cli {
 
     fn handle_cmd() -> ArgMatches {
         command!()
+            .arg(
+                arg!(-v --verbose "Show the commands surtur runs (-vv for more details)")
+                    .action(ArgAction::Count)
+                    .global(true),
+            )
+            .arg(
+                arg!(-q --quiet "Only show errors and the output of your program")
+                    .conflicts_with("verbose")
+                    .global(true),
+            )
+            .arg(
+                arg!(--"dry-run" "Print the commands that would run without running them")
+                    .global(true),
+            )
+            .arg(
+                arg!(--"keep-going" "Keep building everything that doesn't depend on a failed step")
+                    .global(true),
+            )
+            .arg(
+                arg!(--"build-plan" "Print the build graph instead of building")
+                    .global(true),
+            )
+            .arg(
+                arg!(directory: -C <DIR> "Run as if surtur was started in this directory")
+                    .required(false)
+                    .value_parser(value_parser!(PathBuf))
+                    .global(true),
+            )
+            .arg(
+                arg!(--"manifest-path" <PATH> "Path to the project.lua or workspace.lua to use")
+                    .required(false)
+                    .value_parser(value_parser!(PathBuf))
+                    .global(true),
+            )
+            .arg(
+                arg!(--features <FEATURES> "Comma separated list of features to enable")
+                    .required(false)
+                    .action(ArgAction::Append)
+                    .global(true),
+            )
             .subcommand(
                 CCommand::new("run")
                 .about("Run the current binary project")
@@ -95,6 +366,19 @@ impl Cli {
                     arg!(-d --debug "Enable debug mode for this project")
                         .required(false)
                 )
+                .arg(
+                    arg!(--bin <NAME> "Run the specified binary")
+                        .required(false)
+                )
+                .arg(
+                    arg!(--example <NAME> "Run the specified example")
+                        .required(false)
+                        .conflicts_with("bin")
+                )
+                .arg(
+                    arg!(-p --package <MEMBER> "Run the specified workspace member")
+                        .required(false)
+                )
                 .arg(
                     arg!(<PROGRAM_ARGS> ... "Args")
                         .required(false)
@@ -103,6 +387,11 @@ impl Cli {
             .subcommand(
                 CCommand::new("init")
                 .about("Initialize a surtur project in the current directory")
+                .arg(
+                    arg!(--from <BUILD> "Generate project.lua from an existing Make or CMake build")
+                        .required(false)
+                        .value_parser(["make", "cmake"])
+                )
             )
             .subcommand(
                 CCommand::new("build")
@@ -114,22 +403,43 @@ impl Cli {
                     .arg(
                         arg!(-d --debug "Compile the program in debug mode (more advanced debugging capabilities)")
                             .required(false),
+                    )
+                    .arg(
+                        arg!(--bins "Build all binaries of the project")
+                            .required(false),
+                    )
+                    .arg(
+                        arg!(--examples "Build all examples of the project")
+                            .required(false)
+                            .conflicts_with("bins"),
+                    )
+                    .arg(
+                        arg!(-p --package <MEMBER> "Only build the specified workspace member")
+                            .required(false),
                     ),
             )
             .subcommand(
                 subcommand!("test", "Run a specific or all tests",
                 arg!(<NAME> "Specify a test name").required(false))
+                .arg(arg!(-p --package <MEMBER> "Only test the specified workspace member"))
             )
             .subcommand(subcommand!(
                 "add",
-                "Create a new project",
-                arg!(<NAME> "Name for the project")
-            ))
+                "Add a dependency to project.lua",
+                arg!(<DEPENDENCY> "Git url, GitHub `user/repo`, name or path, optionally with @<VERSION>")
+            )
+            .arg(arg!(--dev "Add it to the dev dependencies of tests, examples and benchmarks"))
+            .arg(arg!(--optional "Only use it if the feature with its name is enabled"))
+            .arg(arg!(-l --lib "Add a system library to the `Libraries` table instead"))
+            .arg(arg!(--tag <TAG> "Use a tag of the repository").conflicts_with_all(["rev", "branch"]))
+            .arg(arg!(--rev <REV> "Use a commit of the repository").conflicts_with("branch"))
+            .arg(arg!(--branch <BRANCH> "Use a branch of the repository")))
             .subcommand(subcommand!(
                 "remove",
-                "remove a dependency",
-                arg!(<DEPENDENCY> "dependency to remove")
-            ))
+                "Remove a dependency from project.lua",
+                arg!(<DEPENDENCY> "Name of the dependency to remove")
+            )
+            .arg(arg!(-l --lib "Remove a system library from the `Libraries` table instead")))
             .subcommand(subcommand!(
                 "new",
                 "create a new project",
@@ -140,10 +450,108 @@ impl Cli {
                 .arg(
                     arg!(-f --force "Force update dependencies, even if there is no new version")
                         .required(false))
+                .arg(
+                    arg!(-p --package <NAME> "Only update this dependency, the others keep their locked versions")
+                        .required(false))
             )
             .subcommand(CCommand::new("mem-check")
                 .about("Check your program for memory leaks using valgrind")
             )
+            .subcommand(CCommand::new("bench")
+                .about("Run a specific or all benchmarks from the benches directory")
+                .arg(arg!(<NAME> "Specify a benchmark name").required(false))
+                .arg(
+                    arg!(--warmup <RUNS> "Runs before measuring")
+                        .required(false)
+                        .value_parser(value_parser!(usize))
+                        .default_value("3")
+                )
+                .arg(
+                    arg!(--samples <RUNS> "Measured runs")
+                        .required(false)
+                        .value_parser(value_parser!(usize))
+                        .default_value("10")
+                )
+                .arg(arg!(--"save-baseline" <BASELINE> "Save the results under this name").required(false))
+                .arg(arg!(--baseline <BASELINE> "Compare the results against this saved baseline").required(false))
+            )
+            .subcommand(CCommand::new("check")
+                .about("Check the project for errors without building it")
+                .arg(arg!(--tests "Check the test code as well").required(false))
+                .arg(arg!(-p --package <NAME> "Check only this workspace member").required(false))
+            )
+            .subcommand(CCommand::new("analyze")
+                .about("Run the compiler's static analyzer and write a SARIF report")
+                .arg(arg!(--"save-baseline" "Suppress all current findings in future runs").required(false))
+            )
+            .subcommand(CCommand::new("fmt")
+                .about("Format the project's c and header files with clang-format")
+                .arg(arg!(--check "Only list files that are not formatted").required(false))
+            )
+            .subcommand(CCommand::new("doc")
+                .about("Generate the documentation of the headers in the include directory")
+                .arg(arg!(--markdown "Generate Markdown next to the HTML").required(false))
+            )
+            .subcommand(CCommand::new("install")
+                .about("Build the library in release mode and install it")
+                .arg(
+                    arg!(--prefix <DIR> "Directory to install into")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf))
+                        .default_value(DEFAULT_PREFIX)
+                )
+                .arg(arg!(--uninstall "Remove a previous installation instead").required(false))
+            )
+            .subcommand(CCommand::new("bundle")
+                .about("Build the project in release mode and pack it into build/dist")
+            )
+            .subcommand(CCommand::new("export")
+                .about("Export the build as a Makefile, CMakeLists.txt or build.ninja")
+                .arg(
+                    arg!(--format <FORMAT> "Build system to export to")
+                        .required(true)
+                        .value_parser(["make", "cmake", "ninja"])
+                )
+                .arg(
+                    arg!(-o --output <PATH> "File to write instead of the default one in the project")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf))
+                )
+                .arg(arg!(-f --force "Overwrite build files that were not exported by surtur").required(false))
+            )
+            .subcommand(CCommand::new("clean")
+                .about("Remove build outputs, everything if no option is given")
+                .arg(arg!(-r --release "Only clean the release profile").required(false))
+                .arg(arg!(--debug "Only clean the debug profile").required(false).conflicts_with("release"))
+                .arg(
+                    arg!(--target <KIND> "Only clean one kind of target")
+                        .required(false)
+                        .value_parser(["main", "bins", "examples", "benches"])
+                )
+                .arg(arg!(--deps "Only clean the outputs of dependencies").required(false))
+                .arg(arg!(--dep <NAME> "Only clean the outputs of the specified dependency").required(false))
+                .arg(arg!(--tests "Only clean test binaries").required(false))
+                .arg(arg!(--stale "Only clean objects of files that are no longer sources of the project").required(false))
+            )
+            .subcommand(CCommand::new("watch")
+                .about("Rebuild, test or run the project whenever it changes")
+                .arg(
+                    arg!(<ACTION> "Action to run on changes")
+                        .required(false)
+                        .value_parser(["build", "test", "run"])
+                        .default_value("build")
+                )
+                .arg(
+                    arg!(-x --exec <CMD> "Run a custom shell command instead")
+                        .required(false)
+                )
+                .arg(
+                    arg!(--debounce <MS> "Milliseconds without changes before the action runs")
+                        .required(false)
+                        .value_parser(value_parser!(u64))
+                        .default_value("300")
+                )
+            )
             .get_matches()
     }
 
@@ -151,30 +559,303 @@ impl Cli {
         let cmd = m
             .subcommand_matches("test")
             .context("Failed to match subcommand `test`")?;
-        let tests = cmd.get_one::<PathBuf>("NAME");
-        executor::run_test(
-            self,
-            &match tests {
-                Some(tests) => tests.to_string_lossy().to_string(),
-                None => "*".into(),
-            },
-        )
+        let tests = match cmd.get_one::<PathBuf>("NAME") {
+            Some(tests) => tests.to_string_lossy().to_string(),
+            None => "*".into(),
+        };
+        match (
+            self.members_for(cmd.get_one::<String>("package"))?,
+            cmd.get_one::<String>("package"),
+        ) {
+            (Some(ws), Some(name)) => {
+                let member = ws.into_member(name)?;
+                let code = executor::test_proj(&member.dir, member.cfg, &tests)?;
+                exit(code)
+            }
+            (Some(ws), None) if self.cfg.is_none() => executor::run_workspace_tests(ws, &tests),
+            (None, Some(_)) => bail!(MISSING_WORKSPACE),
+            _ => {
+                let cfg = self.cfg.context(MISSING_CFG)?;
+                exit(executor::test_proj(&self.cur_dir, cfg, &tests)?)
+            }
+        }
     }
 
     fn check_mem(self) -> anyhow::Result<()> {
         let name = self.cfg.as_ref().unwrap().name.clone();
-        executor::build_c(self, true, true, false)?;
+        executor::build_c(self, Target::Main, true, true, false)?;
         let mut cmd = Command::new("valgrind");
         cmd.arg("--leak-check=full");
         cmd.arg("--show-leak-kinds=all");
-        cmd.arg(format!("./build/{}", name));
+        cmd.arg(util::target_dir().join(name));
         cmd.spawn()?.wait()?;
         Ok(())
     }
 
-    fn update(&self, forced: bool) -> anyhow::Result<()> {
-        let dep_manager = &self.cfg.as_ref().context(MISSING_CFG)?.deps;
-        dep_manager.download_deps(forced)
+    fn bench(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("bench")
+            .context("Failed to match subcommand `bench`")?;
+        let opts = BenchOpts {
+            // Unwraps are safe because of the default values
+            warmup: *cmd.get_one::<usize>("warmup").unwrap(),
+            samples: *cmd.get_one::<usize>("samples").unwrap(),
+            save_baseline: cmd.get_one::<String>("save-baseline").map(|s| s.as_str()),
+            baseline: cmd.get_one::<String>("baseline").map(|s| s.as_str()),
+        };
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        let name = cmd.get_one::<String>("NAME").map(|s| s.as_str());
+        bench::run_benches(&self.cur_dir, cfg, name, &opts)
+    }
+
+    fn check(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("check")
+            .context("Failed to match subcommand `check`")?;
+        let tests = cmd.get_flag("tests");
+        match (
+            self.members_for(cmd.get_one::<String>("package"))?,
+            cmd.get_one::<String>("package"),
+        ) {
+            (Some(ws), Some(name)) => {
+                let member = ws.into_member(name)?;
+                executor::check_proj(&member.dir, member.cfg, tests)
+            }
+            (Some(ws), None) if self.cfg.is_none() => executor::check_workspace(ws, tests),
+            (None, Some(_)) => bail!(MISSING_WORKSPACE),
+            _ => {
+                let cfg = self.cfg.context(MISSING_CFG)?;
+                executor::check_proj(&self.cur_dir, cfg, tests)
+            }
+        }
+    }
+
+    fn analyze(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("analyze")
+            .context("Failed to match subcommand `analyze`")?;
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        analyzer::analyze_proj(&self.cur_dir, cfg, cmd.get_flag("save-baseline"))
+    }
+
+    fn fmt(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("fmt")
+            .context("Failed to match subcommand `fmt`")?;
+        let check = cmd.get_flag("check");
+        match (self.members_for(None)?, self.cfg) {
+            (_, Some(cfg)) => formatter::format_proj(&self.cur_dir, &cfg, check),
+            (Some(ws), None) => {
+                let mut failed = Vec::new();
+                for member in &ws.members {
+                    if let Err(err) = formatter::format_proj(&member.dir, &member.cfg, check) {
+                        eprintln!("{:#}", err);
+                        failed.push(member.cfg.name.as_str());
+                    }
+                }
+                if !failed.is_empty() {
+                    bail!(
+                        "Formatting failed for workspace members: {}",
+                        failed.join(", ")
+                    );
+                }
+                Ok(())
+            }
+            (None, None) => bail!(MISSING_CFG),
+        }
+    }
+
+    fn doc(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("doc")
+            .context("Failed to match subcommand `doc`")?;
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        let _lock = executor::lock_build_dir()?;
+        let out_dir = documenter::document_proj(&self.cur_dir, &cfg, cmd.get_flag("markdown"))?;
+        log::status(
+            "Finished",
+            format!("documentation in {}", out_dir.join("index.html").display()),
+        );
+        Ok(())
+    }
+
+    fn install(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("install")
+            .context("Failed to match subcommand `install`")?;
+        // Unwrap is safe because of the default value
+        let prefix = cmd.get_one::<PathBuf>("prefix").unwrap();
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        if cmd.get_flag("uninstall") {
+            installer::uninstall_proj(&cfg.name, prefix)
+        } else {
+            installer::install_proj(&self.cur_dir, cfg, prefix)
+        }
+    }
+
+    fn bundle(self) -> anyhow::Result<()> {
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        let archive = bundler::bundle_proj(&self.cur_dir, cfg)?;
+        log::status("Finished", format!("bundle {}", archive.display()));
+        Ok(())
+    }
+
+    fn export(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("export")
+            .context("Failed to match subcommand `export`")?;
+        // Unwraps are safe because the format is required and has possible values
+        let format = ExportFormat::from_str(cmd.get_one::<String>("format").unwrap()).unwrap();
+        let output = cmd.get_one::<PathBuf>("output").map(|path| path.as_path());
+        let cfg = self.cfg.context(MISSING_CFG)?;
+        exporter::export_proj(&self.cur_dir, &cfg, format, output, cmd.get_flag("force"))?;
+        Ok(())
+    }
+
+    fn clean(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("clean")
+            .context("Failed to match subcommand `clean`")?;
+        let cleaner = Cleaner::new(&self.cur_dir)?;
+        let _lock = executor::lock_build_dir()?;
+        let is_release = cmd.get_flag("release");
+        let mut cleaned = false;
+
+        if let Some(kind) = cmd.get_one::<String>("target") {
+            // Unwrap is safe because of the possible values
+            cleaner.clean_target(TargetKind::from_str(kind).unwrap(), is_release)?;
+            cleaned = true;
+        } else if is_release || cmd.get_flag("debug") {
+            cleaner.clean_profile(is_release)?;
+            cleaned = true;
+        }
+
+        if cmd.get_flag("deps") {
+            cleaner.clean_deps()?;
+            cleaned = true;
+        }
+
+        if let Some(name) = cmd.get_one::<String>("dep") {
+            cleaner.clean_dep(name)?;
+            cleaned = true;
+        }
+
+        if cmd.get_flag("tests") {
+            cleaner.clean_tests()?;
+            cleaned = true;
+        }
+
+        if cmd.get_flag("stale") {
+            let cfg = self.cfg.as_ref().context(MISSING_CFG)?;
+            cleaner.clean_stale(&self.cur_dir, cfg)?;
+            cleaned = true;
+        }
+
+        if !cleaned {
+            cleaner.clean_all()?;
+        }
+
+        Ok(())
+    }
+
+    fn watch(self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("watch")
+            .context("Failed to match subcommand `watch`")?;
+        // Unwraps are safe because of the default values
+        let action = match (
+            cmd.get_one::<String>("exec"),
+            cmd.get_one::<String>("ACTION").unwrap().as_str(),
+        ) {
+            (Some(shell_cmd), _) => WatchAction::Exec(shell_cmd.clone()),
+            (None, "test") => WatchAction::Test,
+            (None, "run") => WatchAction::Run,
+            (None, _) => WatchAction::Build,
+        };
+        let debounce = Duration::from_millis(*cmd.get_one::<u64>("debounce").unwrap());
+        self.cfg.as_ref().context(MISSING_CFG)?;
+        Watcher::new(&self.cur_dir, action, debounce).watch()
+    }
+
+    fn update(&self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("update")
+            .context("Failed to match subcommand `update`")?;
+        let forced = cmd.get_flag("force");
+        let update = match cmd.get_one::<String>("package") {
+            Some(pkg) => Update::Package(pkg.clone()),
+            None => Update::All,
+        };
+        match (&self.cfg, self.members_for(None)?) {
+            (Some(cfg), _) => cfg.deps.update(update, forced),
+            (None, Some(ws)) => ws.deps().update(update, forced),
+            (None, None) => bail!(MISSING_CFG),
+        }
+    }
+
+    fn add(&self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("add")
+            .context("Failed to match subcommand `add`")?;
+        self.cfg.as_ref().context(MISSING_CFG)?;
+        let opts = AddOpts {
+            dev: cmd.get_flag("dev"),
+            optional: cmd.get_flag("optional"),
+            lib: cmd.get_flag("lib"),
+            tag: cmd.get_one::<String>("tag").cloned(),
+            rev: cmd.get_one::<String>("rev").cloned(),
+            branch: cmd.get_one::<String>("branch").cloned(),
+        };
+        // Unwrap is safe because the dependency is required
+        let spec = cmd
+            .get_one::<PathBuf>("DEPENDENCY")
+            .unwrap()
+            .to_string_lossy();
+        let original = fs::read_to_string(self.cur_dir.join(CONFIG_FILE))?;
+        match editor::add_dep(&self.cur_dir, &spec, &opts)? {
+            Some(name) => self.relock(Update::Package(name), &original),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, m: ArgMatches) -> anyhow::Result<()> {
+        let cmd = m
+            .subcommand_matches("remove")
+            .context("Failed to match subcommand `remove`")?;
+        self.cfg.as_ref().context(MISSING_CFG)?;
+        // Unwrap is safe because the dependency is required
+        let name = cmd
+            .get_one::<PathBuf>("DEPENDENCY")
+            .unwrap()
+            .to_string_lossy();
+        let original = fs::read_to_string(self.cur_dir.join(CONFIG_FILE))?;
+        if editor::remove_dep(&self.cur_dir, &name, cmd.get_flag("lib"))? {
+            self.relock(Update::None, &original)?;
+            let _lock = executor::lock_build_dir()?;
+            Cleaner::new(&self.cur_dir)?.clean_dep(&name)?;
+        }
+        Ok(())
+    }
+
+    /// Updates the lockfile after project.lua was edited. If that fails the
+    /// edit is undone, so project.lua and the lockfile don't disagree
+    fn relock(&self, update: Update, original: &str) -> anyhow::Result<()> {
+        self.update_edited(update).inspect_err(|_| {
+            let _ = fs::write(self.cur_dir.join(CONFIG_FILE), original);
+        })
+    }
+
+    /// Updates the lockfile with the edited project.lua, with
+    /// the dependencies of all members if it's in a workspace
+    fn update_edited(&self, update: Update) -> anyhow::Result<()> {
+        if let Some(ws) = Self::find_workspace(&self.cur_dir)? {
+            return ws.load(&self.features)?.deps().update(update, false);
+        }
+        let cfg = Config::parse(
+            &self.cur_dir,
+            FileHandler::new(&self.cur_dir.join(CONFIG_FILE))?,
+        )?;
+        cfg.deps.update(update, false)
     }
 
     fn new_proj(m: ArgMatches) -> anyhow::Result<()> {
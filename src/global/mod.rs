@@ -3,13 +3,23 @@
 
 pub mod extensions;
 
-use std::{env, fs, path::PathBuf};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
 use dirs::home_dir;
-use git2::Repository;
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    Oid, Repository,
+};
+use sha2::{Digest, Sha256};
+use tar::Archive;
 
-use crate::tool::deps::Dependency;
+use crate::util::{lock::DirLock, log};
 
 pub const SURTUR_HOME: &str = "SURTUR_HOME";
 
@@ -28,18 +38,177 @@ pub(super) fn init_dir() -> anyhow::Result<()> {
     Ok(())
 }
 
-pub(super) fn download_dep(dep: &Dependency, forced: bool) -> anyhow::Result<()> {
-    let url = &dep.origin;
-    let dep_path = PathBuf::from(env::var(SURTUR_HOME)?)
-        .join("deps")
-        .join(dep.name()?);
-    if !dep_path.exists() {
-        if let Err(err) = Repository::clone(url, dep_path) {
-            eprintln!("{}", err);
+/// Directory for headers that ship with surtur
+pub fn include_dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(env::var(SURTUR_HOME)?).join("include"))
+}
+
+/// Locks the global dependency directory. Builds only read
+/// from it and use a shared lock, downloads need it exclusively
+pub fn lock_deps(shared: bool) -> anyhow::Result<DirLock> {
+    let deps_path = deps_dir()?;
+    if shared {
+        DirLock::shared(&deps_path, "the global dependency directory")
+    } else {
+        DirLock::exclusive(&deps_path, "the global dependency directory")
+    }
+}
+
+/// Directory the dependencies are downloaded to
+pub fn deps_dir() -> anyhow::Result<PathBuf> {
+    Ok(PathBuf::from(env::var(SURTUR_HOME)?).join("deps"))
+}
+
+/// Name a dependency is stored under in the dependency directory. It includes a hash
+/// of the source, so dependencies with the same name from different places are kept apart
+pub fn store_name(name: &str, source: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(source.as_bytes()));
+    format!("{}-{}", name, &hash[..8])
+}
+
+/// Bare clone of a dependency's repository, its versions are checked out from it
+pub fn dep_mirror(url: &str, name: &str) -> anyhow::Result<PathBuf> {
+    Ok(deps_dir()?.join(format!("{}.git", store_name(name, url))))
+}
+
+/// Clones the repository of a dependency or fetches its new
+/// tags and branches, `forced` throws away the old clone
+pub(crate) fn fetch_dep(url: &str, name: &str, forced: bool) -> anyhow::Result<()> {
+    let _lock = lock_deps(false)?;
+    fetch_mirror(url, name, forced)
+}
+
+/// Same as `fetch_dep`, but the caller has to hold the dependency lock
+fn fetch_mirror(url: &str, name: &str, forced: bool) -> anyhow::Result<()> {
+    let mirror = dep_mirror(url, name)?;
+    if mirror.exists() && forced {
+        fs::remove_dir_all(&mirror)?;
+    }
+    if !mirror.exists() {
+        RepoBuilder::new()
+            .bare(true)
+            .clone(url, &mirror)
+            .context(format!("Failed to clone {}", url))?;
+        return Ok(());
+    }
+    let repo = Repository::open_bare(&mirror)?;
+    repo.remote_anonymous(url)?
+        .fetch(&["+refs/tags/*:refs/tags/*", "+refs/heads/*:refs/heads/*"], None, None)
+        .context(format!("Failed to fetch {}", url))?;
+    Ok(())
+}
+
+/// Commit a tag, branch or abbreviated commit of a dependency's mirror points to
+pub(crate) fn resolve_rev(url: &str, name: &str, rev: &str) -> anyhow::Result<String> {
+    let repo = Repository::open_bare(dep_mirror(url, name)?)?;
+    let commit = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .context(format!("Failed to find {} in the repository of {}", rev, name))?;
+    Ok(commit.id().to_string())
+}
+
+/// Commit a checked out dependency is at
+fn checked_out_commit(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// Checks out a commit of a dependency into `path`, unless it already is or
+/// `forced` is set. The repository is fetched if the mirror doesn't have the
+/// commit yet. Returns whether anything was checked out. The caller has to hold
+/// the exclusive dependency lock, so no other build uses the checkout meanwhile
+pub(crate) fn checkout_dep(
+    url: &str,
+    name: &str,
+    commit: &str,
+    path: &Path,
+    forced: bool,
+) -> anyhow::Result<bool> {
+    if !forced && checked_out_commit(path).as_deref() == Some(commit) {
+        return Ok(false);
+    }
+    let mirror = dep_mirror(url, name)?;
+    let oid = Oid::from_str(commit).context(format!("Invalid commit {} of {}", commit, name))?;
+    let has_commit = Repository::open_bare(&mirror).is_ok_and(|repo| repo.find_commit(oid).is_ok());
+    if !has_commit {
+        fetch_mirror(url, name, false)?;
+    }
+
+    if path.exists() {
+        fs::remove_dir_all(path)?;
+    }
+    // Checked out next to the final path first, so failed checkouts don't leave anything behind
+    let tmp_path = path.with_file_name(format!(
+        ".{}.tmp",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+    let repo = Repository::clone(&mirror.to_string_lossy(), &tmp_path)
+        .context(format!("Failed to check out {} at {}", name, commit))?;
+    repo.find_remote("origin")?
+        .fetch(&["+refs/tags/*:refs/tags/*"], None, None)?;
+    repo.find_commit(oid)
+        .context(format!("Failed to find commit {} of {}", commit, name))?;
+    repo.set_head_detached(oid)?;
+    repo.checkout_head(Some(CheckoutBuilder::new().force()))?;
+    drop(repo);
+    fs::rename(&tmp_path, path)?;
+    Ok(true)
+}
+
+/// Downloads an archive, checks it against its checksum and extracts it to `path`
+pub(crate) fn fetch_tarball(url: &str, sha256: &str, path: &Path) -> anyhow::Result<()> {
+    // Checked while holding the lock, another build might be extracting it right now
+    let _lock = lock_deps(false)?;
+    if path.exists() {
+        return Ok(());
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let archive_path = path.with_file_name(format!(".{}.tar.gz", file_name));
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    log::status("Downloading", url);
+    let output = Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+        .arg(&archive_path)
+        .arg(url)
+        .output()
+        .context("Failed to run curl, it is needed to download archives")?;
+    if !output.status.success() {
+        bail!(
+            "Failed to download {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let content = fs::read(&archive_path)?;
+    fs::remove_file(&archive_path)?;
+    let actual = format!("{:x}", Sha256::digest(&content));
+    if !actual.eq_ignore_ascii_case(sha256) {
+        bail!("Checksum of {} is {}, but {} was expected", url, actual, sha256);
+    }
+
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+    Archive::new(GzDecoder::new(content.as_slice()))
+        .unpack(&tmp_path)
+        .context(format!("Failed to extract {}", url))?;
+    // Most archives have a single directory with everything in it
+    let entries: Vec<PathBuf> = fs::read_dir(&tmp_path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    match entries.as_slice() {
+        [dir] if dir.is_dir() => {
+            fs::rename(dir, path)?;
+            fs::remove_dir_all(&tmp_path)?;
         }
-    } else if dep_path.exists() && forced {
-        fs::remove_dir_all(&dep_path)?;
-        Repository::clone(url, dep_path)?;
+        _ => fs::rename(&tmp_path, path)?,
     }
     Ok(())
 }
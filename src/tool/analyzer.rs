@@ -0,0 +1,287 @@
+//! Handling of running the compiler's static analyzer
+//! over the project. Findings of all translation units
+//! are deduplicated, compared against a baseline of known
+//! findings and written to a SARIF report for code scanning tools.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context};
+use colored::Colorize;
+use mlua::{Lua, Table};
+use serde_json::{json, Value};
+
+use crate::util::{self, glob, log, lua_utils::lua_string};
+
+use super::{
+    compiler::{CompileCtx, Compiler},
+    config::Config,
+};
+
+/// Known findings that are not reported, relative to the project
+pub const BASELINE_FILE: &str = "analyze-baseline.lua";
+
+pub const SARIF_FILE: &str = "analyze.sarif";
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Finding {
+    /// Path relative to the project if the file is part of it
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub level: String,
+    pub rule: String,
+    pub message: String,
+}
+
+impl Finding {
+    /// Parses a diagnostic line like
+    /// `src/main.c:5:3: warning: double-'free' of 'p' [CWE-415] [-Wanalyzer-double-free]`
+    pub fn parse(line: &str, root_dir: &Path) -> Option<Self> {
+        let (location, level, rest) = ["warning", "error"].iter().find_map(|level| {
+            let (location, rest) = line.split_once(&format!(": {}: ", level))?;
+            Some((location, *level, rest))
+        })?;
+
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next()?.parse().ok()?;
+        let line = parts.next()?.parse().ok()?;
+        let path = Path::new(parts.next()?);
+
+        // The rule is the last bracketed part, e.g. [-Wanalyzer-double-free] or [unix.Malloc]
+        let (message, rule) = match rest.strip_suffix(']').and_then(|rest| rest.rsplit_once(" [")) {
+            Some((message, rule)) => (message, rule.trim_start_matches("-W")),
+            None => (rest, level),
+        };
+
+        Some(Self {
+            file: glob::relative(path, root_dir).unwrap_or_else(|| path.display().to_string()),
+            line,
+            column,
+            level: level.into(),
+            rule: rule.into(),
+            message: message.into(),
+        })
+    }
+
+    fn is_suppressed(&self, baseline: &[Suppression]) -> bool {
+        baseline.iter().any(|suppression| {
+            suppression.rule == self.rule
+                && suppression.file == self.file
+                && suppression.message == self.message
+        })
+    }
+
+    fn print(&self) {
+        let level = if self.level == "error" {
+            self.level.red().bold()
+        } else {
+            self.level.yellow().bold()
+        };
+        println!("{}: {} [{}]", level, self.message.bold(), self.rule);
+        println!("  {} {}:{}:{}", "-->".cyan().bold(), self.file, self.line, self.column);
+    }
+}
+
+/// Known finding, the line is left out so the finding
+/// stays suppressed when code around it changes
+#[derive(Debug)]
+struct Suppression {
+    rule: String,
+    file: String,
+    message: String,
+}
+
+fn load_baseline(path: &Path) -> anyhow::Result<Vec<Suppression>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let lua = Lua::new();
+    lua.load(fs::read_to_string(path)?)
+        .exec()
+        .context(format!("Failed to load analyzer baseline {}", path.display()))?;
+    let table: Table = lua
+        .globals()
+        .get("Suppressed")
+        .context("Analyzer baseline requires a `Suppressed` table")?;
+    let mut baseline = Vec::new();
+    for entry in table.sequence_values::<Table>() {
+        let entry = entry.context("Failed to get suppressed finding")?;
+        baseline.push(Suppression {
+            rule: entry.get("rule")?,
+            file: entry.get("file")?,
+            message: entry.get("message")?,
+        });
+    }
+    Ok(baseline)
+}
+
+fn save_baseline(path: &Path, findings: &BTreeSet<Finding>) -> anyhow::Result<()> {
+    let mut content = String::from("-- Known findings of `surtur analyze` that are not reported\nSuppressed = {\n");
+    let mut saved = BTreeSet::new();
+    for finding in findings {
+        if saved.insert((&finding.rule, &finding.file, &finding.message)) {
+            content.push_str(&format!(
+                "    {{ rule = {}, file = {}, message = {} }},\n",
+                lua_string(&finding.rule),
+                lua_string(&finding.file),
+                lua_string(&finding.message)
+            ));
+        }
+    }
+    content.push_str("}\n");
+    fs::write(path, content).context(format!("Failed to save analyzer baseline to {}", path.display()))
+}
+
+/// Writes the findings as a SARIF 2.1.0 log, suppressed findings
+/// are kept but marked as such
+fn write_sarif(
+    path: &Path,
+    tool: &str,
+    findings: &BTreeSet<Finding>,
+    baseline: &[Suppression],
+) -> anyhow::Result<()> {
+    let rules: BTreeSet<&str> = findings.iter().map(|finding| finding.rule.as_str()).collect();
+    let rules: Vec<Value> = rules.iter().map(|rule| json!({ "id": rule })).collect();
+
+    let results: Vec<Value> = findings
+        .iter()
+        .map(|finding| {
+            let mut result = json!({
+                "ruleId": finding.rule,
+                "level": finding.level,
+                "message": { "text": finding.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": finding.file },
+                        "region": { "startLine": finding.line, "startColumn": finding.column },
+                    },
+                }],
+            });
+            if finding.is_suppressed(baseline) {
+                result["suppressions"] = json!([{ "kind": "external" }]);
+            }
+            result
+        })
+        .collect();
+
+    let sarif = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": { "driver": { "name": tool, "rules": rules } },
+            "results": results,
+        }],
+    });
+    let sarif = format!("{}\n", sarif);
+    fs::write(path, sarif).context(format!("Failed to write SARIF report to {}", path.display()))
+}
+
+/// Runs the static analyzer over every translation unit of the project.
+/// Fails if there are findings that are not part of the baseline
+pub fn analyze_proj(proj_dir: &PathBuf, cfg: Config, update_baseline: bool) -> anyhow::Result<()> {
+    let _lock = super::executor::lock_build_dir()?;
+    let compiler = Compiler::new(proj_dir, &cfg)?;
+    let root_name = compiler.root_name;
+    let sources = cfg.active_sources();
+    let ctx = CompileCtx {
+        out_dir: proj_dir,
+        root_dir: proj_dir,
+        out_name: root_name,
+        sources: &sources,
+        objects: &cfg.objects,
+    };
+
+    // Objects are only written because the analyzer runs after parsing
+    let out_dir = util::target_dir().join("analyze");
+    fs::create_dir_all(&out_dir).context("Failed to create build/analyze directory")?;
+
+    let units = compiler.translation_units(&ctx);
+    let mut cmds = Vec::new();
+    for (i, file) in units.iter().enumerate() {
+        let mut program = compiler.unit_cmd(file, false)?;
+        if compiler.is_clang() {
+            program.args(["--analyze", "-Xclang", "-analyzer-output=text"]);
+        } else {
+            program.args(["-c", "-fanalyzer"]);
+        }
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        program
+            .arg("-fdiagnostics-color=never")
+            .arg("-o")
+            .arg(out_dir.join(format!("{}-{}.o", i, name)));
+        cmds.push(program);
+    }
+
+    log::status("Analyzing", format!("{} ({} files)", root_name, units.len()));
+
+    let findings = Mutex::new(BTreeSet::new());
+    let failed = Mutex::new(0);
+    util::exec_parallel_with(cmds, |_, output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        log::verbose(stderr.trim_end());
+        if !output.status.success() {
+            eprint!("{}", stderr);
+            *failed.lock().unwrap() += 1;
+        }
+        let mut findings = findings.lock().unwrap();
+        findings.extend(stderr.lines().filter_map(|line| Finding::parse(line, proj_dir)));
+    })?;
+
+    if log::is_dry_run() {
+        return Ok(());
+    }
+
+    let failed = failed.into_inner().unwrap();
+    if failed != 0 {
+        bail!("Could not analyze `{}` due to errors in {} file(s)", root_name, failed);
+    }
+
+    let findings = findings.into_inner().unwrap();
+    let baseline_path = proj_dir.join(BASELINE_FILE);
+
+    if update_baseline {
+        save_baseline(&baseline_path, &findings)?;
+        log::status("Saved", format!("{} finding(s) to {}", findings.len(), baseline_path.display()));
+        return Ok(());
+    }
+
+    let baseline = load_baseline(&baseline_path)?;
+    let tool = if compiler.is_clang() {
+        "clang --analyze"
+    } else {
+        "gcc -fanalyzer"
+    };
+    let sarif_path = util::target_dir().join(SARIF_FILE);
+    write_sarif(&sarif_path, tool, &findings, &baseline)?;
+
+    let new: Vec<&Finding> = findings
+        .iter()
+        .filter(|finding| !finding.is_suppressed(&baseline))
+        .collect();
+    for finding in &new {
+        finding.print();
+    }
+
+    log::status(
+        "Finished",
+        format!(
+            "analyzing {}: {} finding(s), {} suppressed, report written to {}",
+            root_name,
+            new.len(),
+            findings.len() - new.len(),
+            sarif_path.display()
+        ),
+    );
+
+    if !new.is_empty() {
+        bail!("`{}` has {} new finding(s)", root_name, new.len());
+    }
+    Ok(())
+}
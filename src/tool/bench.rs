@@ -0,0 +1,252 @@
+//! Handling of benchmarks. Every c file in the benches
+//! directory is built in release mode and executed
+//! multiple times to measure how long it takes.
+//! Results are stored as lua files in build/bench
+//! so later runs can be compared against them.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use colored::Colorize;
+use mlua::Lua;
+
+use crate::{
+    global,
+    util::{self, log},
+};
+
+use super::{
+    config::Config,
+    executor::{self, Target},
+};
+
+pub const BENCH_HEADER_NAME: &str = "surtur_bench.h";
+
+const BENCH_HEADER: &str = r#"// Timing helpers for surtur benchmarks
+#ifndef SURTUR_BENCH_H
+#define SURTUR_BENCH_H
+
+#include <stdint.h>
+#include <stdio.h>
+#include <time.h>
+
+// Current time in nanoseconds
+static inline uint64_t surtur_bench_now(void) {
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (uint64_t)ts.tv_sec * 1000000000ull + (uint64_t)ts.tv_nsec;
+}
+
+// Prints the time that passed since start (taken with surtur_bench_now)
+static inline void surtur_bench_report(const char *name, uint64_t start) {
+    uint64_t elapsed = surtur_bench_now() - start;
+    printf("%s: %llu ns\n", name, (unsigned long long)elapsed);
+}
+
+// Prevents the compiler from optimizing away a value
+#define SURTUR_BLACK_BOX(value) __asm__ volatile("" : : "g"(value) : "memory")
+
+// Runs the statement n times
+#define SURTUR_BENCH_ITER(n, stmt)                                             \
+    for (uint64_t surtur_iter_ = 0; surtur_iter_ < (n); surtur_iter_++) {     \
+        stmt;                                                                  \
+    }
+
+#endif
+"#;
+
+/// Baseline that every run is saved as and compared against by default
+const LAST_BASELINE: &str = "last";
+
+pub struct BenchOpts<'b> {
+    pub warmup: usize,
+    pub samples: usize,
+    pub save_baseline: Option<&'b str>,
+    pub baseline: Option<&'b str>,
+}
+
+/// Timings of a benchmark in seconds
+pub struct Stats {
+    pub mean: f64,
+    pub median: f64,
+    pub deviation: f64,
+}
+
+impl Stats {
+    pub fn new(samples: &[f64]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let len = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / len;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0
+        } else {
+            sorted[sorted.len() / 2]
+        };
+        let variance = if sorted.len() > 1 {
+            sorted.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (len - 1.0)
+        } else {
+            0.0
+        };
+        Self {
+            mean,
+            median,
+            deviation: variance.sqrt(),
+        }
+    }
+
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let lua = Lua::new();
+        lua.load(fs::read_to_string(path)?).exec()?;
+        Ok(Self {
+            mean: lua.globals().get("Mean")?,
+            median: lua.globals().get("Median")?,
+            deviation: lua.globals().get("Deviation")?,
+        })
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(
+            path,
+            format!(
+                "Mean = {}\nMedian = {}\nDeviation = {}\n",
+                self.mean, self.median, self.deviation
+            ),
+        )
+        .context(format!("Failed to save benchmark results to {}", path.display()))
+    }
+}
+
+/// Writes the benchmark header to surtur's include directory
+pub fn install_header() -> anyhow::Result<()> {
+    let include_dir = global::include_dir()?;
+    fs::create_dir_all(&include_dir)?;
+    let path = include_dir.join(BENCH_HEADER_NAME);
+    if fs::read_to_string(&path).ok().as_deref() != Some(BENCH_HEADER) {
+        fs::write(&path, BENCH_HEADER).context("Failed to write benchmark header")?;
+    }
+    Ok(())
+}
+
+/// Builds and runs a specific or all benchmarks of the project
+pub fn run_benches(
+    proj_dir: &PathBuf,
+    mut cfg: Config,
+    name: Option<&str>,
+    opts: &BenchOpts,
+) -> anyhow::Result<()> {
+    install_header()?;
+
+    let mut names: Vec<String> = match name {
+        Some(name) => vec![name.into()],
+        None => cfg.benches.keys().cloned().collect(),
+    };
+    names.sort();
+
+    if names.is_empty() {
+        log::warn("No benchmarks found in benches/");
+        return Ok(());
+    }
+
+    let target = match name {
+        Some(name) => Target::Bench(name),
+        None => Target::Benches,
+    };
+
+    executor::build_proj(proj_dir, &mut cfg, target, false, false, true)?;
+
+    if log::is_dry_run() {
+        return Ok(());
+    }
+
+    for name in names {
+        run_bench(proj_dir, &name, opts)?;
+    }
+
+    Ok(())
+}
+
+fn run_bench(proj_dir: &PathBuf, name: &str, opts: &BenchOpts) -> anyhow::Result<()> {
+    let bench_dir = util::target_dir().join("bench");
+    let exe = bench_dir.join("bin").join(name);
+
+    for _ in 0..opts.warmup {
+        run_once(&exe, proj_dir)?;
+    }
+
+    let samples = (0..opts.samples.max(1))
+        .map(|_| run_once(&exe, proj_dir))
+        .collect::<anyhow::Result<Vec<f64>>>()?;
+    let stats = Stats::new(&samples);
+
+    println!(
+        "{} {}: mean {}, median {}, deviation ±{} ({} samples)",
+        "bench".green(),
+        name,
+        fmt_secs(stats.mean),
+        fmt_secs(stats.median),
+        fmt_secs(stats.deviation),
+        samples.len()
+    );
+
+    let baselines_dir = bench_dir.join("baselines");
+    let baseline = opts.baseline.unwrap_or(LAST_BASELINE);
+    let baseline_path = baselines_dir.join(baseline).join(format!("{}.lua", name));
+
+    if baseline_path.exists() {
+        let old = Stats::load(&baseline_path)
+            .context(format!("Failed to load baseline `{}`", baseline))?;
+        let change = (stats.mean - old.mean) / old.mean * 100.0;
+        // Only report differences that are larger than the noise of both runs
+        let significant =
+            (stats.mean - old.mean).abs() > old.deviation + stats.deviation && change.abs() > 2.0;
+        let verdict = match (significant, change > 0.0) {
+            (false, _) => "no change".normal(),
+            (true, true) => "regressed".red(),
+            (true, false) => "improved".green(),
+        };
+        println!(
+            "      compared to `{}` ({}): {:+.2}% {}",
+            baseline,
+            fmt_secs(old.mean),
+            change,
+            verdict
+        );
+    } else if opts.baseline.is_some() {
+        bail!("Failed to find baseline `{}` for benchmark `{}`", baseline, name);
+    }
+
+    stats.save(&baselines_dir.join(LAST_BASELINE).join(format!("{}.lua", name)))?;
+    if let Some(save) = opts.save_baseline {
+        stats.save(&baselines_dir.join(save).join(format!("{}.lua", name)))?;
+    }
+
+    Ok(())
+}
+
+/// Runs the benchmark once and returns how long it took in seconds
+fn run_once(exe: &Path, proj_dir: &PathBuf) -> anyhow::Result<f64> {
+    let mut cmd = Command::new(exe);
+    cmd.env("SURTUR_PROJ_DIR", proj_dir).stdout(Stdio::null());
+    let start = Instant::now();
+    let status = cmd
+        .status()
+        .context(format!("Failed to run benchmark {}", exe.display()))?;
+    let elapsed = start.elapsed();
+    if !status.success() {
+        bail!("Benchmark {} exited with {}", exe.display(), status);
+    }
+    Ok(elapsed.as_secs_f64())
+}
+
+fn fmt_secs(secs: f64) -> String {
+    format!("{:.2?}", Duration::from_secs_f64(secs))
+}
@@ -0,0 +1,174 @@
+//! Handling of bundling a project into a distributable
+//! archive. The release build of the project is packed
+//! together with its headers, README and LICENSE files
+//! and a manifest into a tar.gz with a SHA-256 checksum.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+
+use crate::util::{self, log, lua_utils::lua_string};
+
+use super::{
+    config::{Config, ProjType},
+    executor::{self, Target},
+};
+
+pub const DIST_DIR: &str = "dist";
+
+pub const MANIFEST_FILE: &str = "manifest.lua";
+
+/// Files of the project root that are shipped with every bundle
+const DOC_FILE_PREFIXES: [&str; 3] = ["README", "LICENSE", "COPYING"];
+
+/// Generates the manifest of the bundle
+fn manifest(cfg: &Config, target: &str) -> anyhow::Result<String> {
+    let mut content = format!(
+        "Name = {}\nVersion = {}\nTarget = {}\nType = {}\n\nDependencies = {{\n",
+        lua_string(&cfg.name),
+        lua_string(&cfg.props.proj_version),
+        lua_string(target),
+        lua_string(&cfg.props.proj_type.to_string())
+    );
+    for pkg in cfg.deps.resolve(&cfg.features, false)? {
+        let _ = writeln!(
+            content,
+            "    {{ name = {}, source = {}, revision = {} }},",
+            lua_string(&pkg.name),
+            lua_string(&pkg.dep.source.to_string()),
+            lua_string(pkg.locked.as_ref().map_or("unknown", |locked| &locked.commit))
+        );
+    }
+    content.push_str("}\n");
+    Ok(content)
+}
+
+/// Files of the release build that go into the bundle, with their path in it
+fn build_outputs(proj_dir: &Path, cfg: &Config) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let out_dir = executor::profile_dir(true);
+    let root_name = util::root_dir_name(proj_dir).context("Failed to get root name of project")?;
+    let mut files = Vec::new();
+
+    match cfg.props.proj_type {
+        ProjType::Bin => {
+            files.push((out_dir.join(root_name), PathBuf::from("bin").join(&cfg.name)));
+            let mut bins: Vec<&String> = cfg.binaries.keys().collect();
+            bins.sort();
+            for bin in bins {
+                files.push((out_dir.join("bin").join(bin), PathBuf::from("bin").join(bin)));
+            }
+        }
+        ProjType::Lib => {
+            files.push((
+                out_dir.join(format!("{}.a", root_name)),
+                PathBuf::from("lib").join(format!("lib{}.a", cfg.name)),
+            ));
+        }
+    }
+
+    let include_dir = proj_dir.join("include");
+    if include_dir.exists() {
+        let mut headers: Vec<PathBuf> = util::get_header_files(&include_dir).into_iter().collect();
+        headers.sort();
+        for header in headers {
+            let rel = header.strip_prefix(&include_dir)?.to_path_buf();
+            files.push((header, PathBuf::from("include").join(rel)));
+        }
+    }
+
+    let mut docs: Vec<PathBuf> = fs::read_dir(proj_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path.file_name().is_some_and(|name| {
+                    let name = name.to_string_lossy().to_uppercase();
+                    DOC_FILE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+                })
+        })
+        .collect();
+    docs.sort();
+    for doc in docs {
+        let name = PathBuf::from(doc.file_name().unwrap());
+        files.push((doc, name));
+    }
+    Ok(files)
+}
+
+fn sha256(path: &Path) -> anyhow::Result<String> {
+    let mut file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).context(format!("Failed to hash {}", path.display()))?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Builds the project in release mode and packs it into
+/// build/dist/<name>-<version>-<target>.tar.gz
+pub fn bundle_proj(proj_dir: &PathBuf, mut cfg: Config) -> anyhow::Result<PathBuf> {
+    let target = cfg.target();
+    let bundle_name = format!("{}-{}-{}", cfg.name, cfg.props.proj_version, target);
+    let manifest = manifest(&cfg, &target)?;
+    let files = build_outputs(proj_dir, &cfg)?;
+
+    executor::build_proj(proj_dir, &mut cfg, Target::Main, false, false, true)?;
+    if cfg.props.proj_type == ProjType::Bin && !cfg.binaries.is_empty() {
+        executor::build_proj(proj_dir, &mut cfg, Target::Bins, false, false, true)?;
+    }
+
+    let dist_dir = util::target_dir().join(DIST_DIR);
+    let archive_path = dist_dir.join(format!("{}.tar.gz", bundle_name));
+    log::status("Bundling", archive_path.display());
+    for (_, path) in &files {
+        log::verbose(format!("Adding {}", path.display()));
+    }
+    if log::is_dry_run() {
+        return Ok(archive_path);
+    }
+
+    let _lock = executor::lock_build_dir()?;
+    fs::create_dir_all(&dist_dir).context("Failed to create build/dist directory")?;
+    let archive = File::create(&archive_path)
+        .context(format!("Failed to create {}", archive_path.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+    let root = PathBuf::from(&bundle_name);
+
+    for (file, path) in &files {
+        builder
+            .append_path_with_name(file, root.join(path))
+            .context(format!("Failed to add {} to the bundle", file.display()))?;
+    }
+
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, root.join(MANIFEST_FILE), manifest.as_bytes())
+        .context("Failed to add the manifest to the bundle")?;
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .context(format!("Failed to write {}", archive_path.display()))?;
+
+    let checksum_path = dist_dir.join(format!("{}.tar.gz.sha256", bundle_name));
+    // Same format as sha256sum, so `sha256sum -c` can verify the bundle
+    fs::write(
+        &checksum_path,
+        format!("{}  {}.tar.gz\n", sha256(&archive_path)?, bundle_name),
+    )
+    .context(format!("Failed to write {}", checksum_path.display()))?;
+
+    Ok(archive_path)
+}
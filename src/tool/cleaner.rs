@@ -0,0 +1,193 @@
+//! Handling of removing build outputs. Either everything
+//! in the build directory is removed or only parts
+//! of it, like a profile, a kind of target or the
+//! outputs of dependencies.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::util::{self, glob, lock::LOCK_FILE, log};
+
+use super::{
+    compiler::{self, OBJ_DIR},
+    config::Config,
+    executor::{self, RELEASE_DIR},
+    lockfile::Lockfile,
+};
+
+/// Kinds of targets that can be cleaned on their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Main,
+    Bins,
+    Examples,
+    Benches,
+}
+
+impl TargetKind {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(kind: &str) -> Option<TargetKind> {
+        match kind {
+            "main" => Some(TargetKind::Main),
+            "bins" => Some(TargetKind::Bins),
+            "examples" => Some(TargetKind::Examples),
+            "benches" => Some(TargetKind::Benches),
+            _ => None,
+        }
+    }
+}
+
+pub struct Cleaner<'c> {
+    build_dir: PathBuf,
+    root_name: &'c str,
+}
+
+impl<'c> Cleaner<'c> {
+    pub fn new(cur_dir: &'c Path) -> anyhow::Result<Self> {
+        let root_name =
+            util::root_dir_name(cur_dir).context("Failed to get root name of project")?;
+        Ok(Self {
+            build_dir: util::target_dir(),
+            root_name,
+        })
+    }
+
+    /// Removes everything in the build directory except for its lock file,
+    /// waiting builds would otherwise lock a new file while this one is held
+    pub fn clean_all(&self) -> anyhow::Result<()> {
+        if !self.build_dir.exists() {
+            return Ok(());
+        }
+        log::status("Removing", self.build_dir.display());
+        if log::is_dry_run() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(&self.build_dir)?.flatten() {
+            if entry.file_name() != LOCK_FILE {
+                Self::delete(&entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the outputs of one profile
+    pub fn clean_profile(&self, is_release: bool) -> anyhow::Result<()> {
+        let profile_dir = executor::profile_dir(is_release);
+        if is_release {
+            return Self::remove(&profile_dir);
+        }
+        // Debug outputs live in the root of the build directory,
+        // everything else in there belongs to other profiles or dependencies
+        for kind in [TargetKind::Main, TargetKind::Bins, TargetKind::Examples] {
+            self.clean_target(kind, false)?;
+        }
+        Ok(())
+    }
+
+    pub fn clean_target(&self, kind: TargetKind, is_release: bool) -> anyhow::Result<()> {
+        let profile_dir = executor::profile_dir(is_release);
+        match kind {
+            TargetKind::Main => {
+                Self::remove(&profile_dir.join(self.root_name))?;
+                Self::remove(&profile_dir.join(format!("{}.a", self.root_name)))?;
+                Self::remove(&profile_dir.join(OBJ_DIR))
+            }
+            TargetKind::Bins => Self::remove(&profile_dir.join("bin")),
+            TargetKind::Examples => Self::remove(&profile_dir.join("examples")),
+            TargetKind::Benches => Self::remove(&self.build_dir.join("bench")),
+        }
+    }
+
+    pub fn clean_tests(&self) -> anyhow::Result<()> {
+        Self::remove(&self.build_dir.join("tests"))
+    }
+
+    /// Removes the outputs of all direct and transitive dependencies, which are the
+    /// packages of the lockfile and the directories that hold an archive of their name.
+    /// Nothing is resolved, so cleaning never downloads anything
+    pub fn clean_deps(&self) -> anyhow::Result<()> {
+        let mut names: BTreeSet<String> = Lockfile::load(&util::lockfile())?
+            .packages
+            .into_iter()
+            .map(|pkg| pkg.name)
+            .collect();
+        if let Ok(entries) = fs::read_dir(&self.build_dir) {
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().join(format!("{}.a", name)).is_file() {
+                    names.insert(name);
+                }
+            }
+        }
+        for name in names {
+            self.clean_dep(&name)?;
+        }
+        Ok(())
+    }
+
+    pub fn clean_dep(&self, name: &str) -> anyhow::Result<()> {
+        Self::remove(&self.build_dir.join(name))
+    }
+
+    /// Removes object files that no source of the project compiles to anymore.
+    /// Sources of inactive groups are kept, their objects are still valid
+    pub fn clean_stale(&self, root_dir: &Path, cfg: &Config) -> anyhow::Result<()> {
+        let objects: HashSet<PathBuf> = cfg
+            .sources
+            .iter()
+            .chain(cfg.binaries.values())
+            .chain(cfg.examples.values())
+            .chain(cfg.benches.values())
+            .map(|file| compiler::object_path(Path::new(""), root_dir, file))
+            .collect();
+        let release_dir = self.build_dir.join(RELEASE_DIR);
+        let out_dirs = [
+            self.build_dir.clone(),
+            self.build_dir.join("bin"),
+            self.build_dir.join("examples"),
+            self.build_dir.join("tests"),
+            self.build_dir.join("bench").join("bin"),
+            release_dir.join("bin"),
+            release_dir.join("examples"),
+            release_dir,
+        ];
+        for dir in out_dirs {
+            // Every output has its own directory of objects
+            for target_dir in fs::read_dir(dir.join(OBJ_DIR)).into_iter().flatten().flatten() {
+                let target_dir = target_dir.path();
+                for object in glob::find(&target_dir, "**/*.o") {
+                    let rel = object.strip_prefix(&target_dir)?;
+                    if !objects.contains(rel) {
+                        Self::remove(&object)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        log::status("Removing", path.display());
+        if log::is_dry_run() {
+            return Ok(());
+        }
+        Self::delete(path)
+    }
+
+    fn delete(path: &Path) -> anyhow::Result<()> {
+        if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+        .context(format!("Failed to remove {}", path.display()))
+    }
+}
@@ -2,25 +2,34 @@
 /// This inclues functions for
 /// building, running, linking and bundling libraries.
 use std::{
-    collections::HashSet,
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::Display,
     fs,
-    path::{Path, PathBuf},
+    io::{self, IsTerminal},
+    path::{Component, Path, PathBuf},
     process::Command,
 };
 
 use anyhow::Context;
+use sha2::{Digest, Sha256};
 
-use crate::util;
+use crate::{
+    global,
+    util::{self, log},
+};
 
 use super::{
     config::{Config, ProjType, Properties},
-    deps::DepManager,
+    deps::Package,
+    graph::{Action, BuildGraph, NodeId, NodeKind},
 };
 
 // files to exclude when compiling a c lib by deafult
 pub const DEFAULT_LIB_EXCLUDE: &str = "main.c";
 
+/// Directory next to an output that holds the objects it is linked from
+pub const OBJ_DIR: &str = "obj";
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
 pub enum Standard {
     C89,
@@ -38,6 +47,7 @@ pub enum Standard {
 }
 
 impl Standard {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(c_std: &str) -> Option<Standard> {
         match c_std {
             "c89" => Some(Standard::C89),
@@ -84,10 +94,15 @@ pub enum CompType {
 
 pub struct Compiler<'c> {
     cmd: &'c String,
-    dm: &'c DepManager,
+    packages: Vec<Package>,
     libs: &'c HashSet<String>,
+    bins: &'c HashMap<String, PathBuf>,
+    examples: &'c HashMap<String, PathBuf>,
+    benches: &'c HashMap<String, PathBuf>,
+    entry: &'c PathBuf,
     props: &'c Properties,
-    proj_dir: &'c PathBuf,
+    defines: &'c [String],
+    include_dirs: &'c [PathBuf],
     pub root_name: &'c str,
 }
 
@@ -95,151 +110,520 @@ pub struct CompileCtx<'ctx> {
     pub out_dir: &'ctx Path,
     pub out_name: &'ctx str,
     pub root_dir: &'ctx Path,
-    pub excluded: &'ctx HashSet<PathBuf>,
+    /// Source files that are compiled, without excluded files
+    pub sources: &'ctx HashSet<PathBuf>,
+    pub objects: &'ctx [PathBuf],
+}
+
+/// Language of a source file, gcc is told explicitly
+/// so custom compilers get the right mode as well
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SourceKind {
+    C,
+    Asm,
+    /// Assembly that runs through the preprocessor first (.S)
+    AsmCpp,
+}
+
+impl SourceKind {
+    pub fn from_path(path: &Path) -> SourceKind {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("s") => SourceKind::Asm,
+            Some("S") => SourceKind::AsmCpp,
+            _ => SourceKind::C,
+        }
+    }
+
+    pub fn lang(&self) -> &'static str {
+        match self {
+            SourceKind::C => "c",
+            SourceKind::Asm => "assembler",
+            SourceKind::AsmCpp => "assembler-with-cpp",
+        }
+    }
 }
 
 impl<'c> Compiler<'c> {
-    pub fn new(cur_dir: &'c PathBuf, cfg: &'c Config) -> anyhow::Result<Self> {
+    pub fn new(cur_dir: &'c Path, cfg: &'c Config) -> anyhow::Result<Self> {
         let root_name =
             util::root_dir_name(cur_dir).context("Failed to get root name of project")?;
         Ok(Self {
             cmd: &cfg.props.compiler,
-            dm: &cfg.deps,
+            packages: cfg.deps.resolve(&cfg.features, cfg.dev).context("Failed to resolve dependencies")?,
             props: &cfg.props,
-            proj_dir: cur_dir,
             libs: &cfg.libraries,
+            bins: &cfg.binaries,
+            examples: &cfg.examples,
+            benches: &cfg.benches,
+            entry: &cfg.entry,
+            defines: &cfg.defines,
+            include_dirs: &cfg.include_dirs,
             root_name,
         })
     }
 
+    /// Adds the steps that build the project to the graph, they run after `after`.
+    /// Returns the node of the output, libraries without sources have none
     #[inline(always)]
     pub fn build(
         &self,
+        graph: &mut BuildGraph,
         ctx: CompileCtx<'c>,
         enable_dbg: bool,
         is_release: bool,
         tests: bool,
-    ) -> anyhow::Result<()> {
+        after: &[NodeId],
+    ) -> anyhow::Result<Option<NodeId>> {
         match self.props.proj_type {
-            ProjType::Lib => self.build_lib(ctx),
-            ProjType::Bin => self.build_exe(ctx, enable_dbg, is_release, tests),
+            ProjType::Lib => {
+                let cfg_flags = self.cfg_flags(&|path| path.display().to_string());
+                self.build_lib(graph, ctx, &cfg_flags, after)
+            }
+            ProjType::Bin => self.build_exe(graph, ctx, enable_dbg, is_release, tests, after).map(Some),
         }
     }
 
+    /// Collects all source files of the project that are
+    /// shared between the main executable and the binaries
+    pub fn src_files(&self, ctx: &CompileCtx<'c>) -> HashSet<PathBuf> {
+        let bin_dir = ctx.root_dir.join("src").join("bin");
+        let mut src_files = ctx.sources.clone();
+        src_files.retain(|e| !e.starts_with(&bin_dir) && !self.bins.values().any(|bin| bin == e));
+        src_files
+    }
+
     pub fn build_exe(
         &self,
+        graph: &mut BuildGraph,
         ctx: CompileCtx<'c>,
         enable_dbg: bool,
         is_release: bool,
         tests: bool,
-    ) -> anyhow::Result<()> {
-        let standard = format!("-std={}", self.props.c_std);
-        let mut program = Command::new(self.cmd);
-        let mut src_files = util::get_src_files(&ctx.root_dir.join("src"));
-        src_files.retain(|e| !ctx.excluded.contains(e));
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let src_files = self.src_files(&ctx);
+        self.compile_exe(graph, &ctx, src_files, enable_dbg, is_release, tests, after)
+    }
 
-        if enable_dbg {
-            program.arg("-g");
-        } else if is_release {
-            program.arg("-o3");
+    /// Builds one of the project's binaries. In binary projects the
+    /// binary's file replaces the entry file of the project
+    pub fn build_bin(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: CompileCtx<'c>,
+        bin_name: &str,
+        enable_dbg: bool,
+        is_release: bool,
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let bin_file = self
+            .bins
+            .get(bin_name)
+            .context(format!("Failed to find binary `{}`", bin_name))?;
+        let mut src_files = self.src_files(&ctx);
+        if self.props.proj_type == ProjType::Bin {
+            src_files.remove(&ctx.root_dir.join("src").join(self.entry));
         }
+        src_files.insert(bin_file.clone());
+        self.compile_exe(graph, &ctx, src_files, enable_dbg, is_release, false, after)
+    }
 
-        program
-            .args(src_files)
-            .arg("-o")
-            .arg(ctx.out_dir.join(ctx.out_name));
+    /// Builds one of the project's examples. Examples are linked
+    /// against the project's sources without its main file
+    pub fn build_example(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: CompileCtx<'c>,
+        example_name: &str,
+        enable_dbg: bool,
+        is_release: bool,
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let example_file = self
+            .examples
+            .get(example_name)
+            .context(format!("Failed to find example `{}`", example_name))?;
+        self.build_against_srcs(graph, ctx, example_file, enable_dbg, is_release, after)
+    }
 
-        if !tests {
-            program.arg("-DNOTESTS");
+    /// Builds one of the project's benchmarks in release mode
+    pub fn build_bench(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: CompileCtx<'c>,
+        bench_name: &str,
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let bench_file = self
+            .benches
+            .get(bench_name)
+            .context(format!("Failed to find benchmark `{}`", bench_name))?;
+        self.build_against_srcs(graph, ctx, bench_file, false, true, after)
+    }
+
+    /// Builds the file together with the project's
+    /// sources, excluding the project's main file
+    fn build_against_srcs(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: CompileCtx<'c>,
+        file: &Path,
+        enable_dbg: bool,
+        is_release: bool,
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let src_dir = ctx.root_dir.join("src");
+        let mut src_files = self.src_files(&ctx);
+        src_files.remove(&src_dir.join(DEFAULT_LIB_EXCLUDE));
+        if self.props.proj_type == ProjType::Bin {
+            src_files.remove(&src_dir.join(self.entry));
         }
+        src_files.insert(file.to_path_buf());
+        self.compile_exe(graph, &ctx, src_files, enable_dbg, is_release, false, after)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn compile_exe(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: &CompileCtx<'c>,
+        src_files: HashSet<PathBuf>,
+        enable_dbg: bool,
+        is_release: bool,
+        tests: bool,
+        after: &[NodeId],
+    ) -> anyhow::Result<NodeId> {
+        let obj_dir = obj_dir(ctx);
+        let mut src_files: Vec<PathBuf> = src_files.into_iter().collect();
+        src_files.sort();
+        let mut objects = Vec::new();
+        let mut compiled = Vec::new();
 
-        println!("command: {:#?} {:#?}", self.cmd, program.get_args());
+        for file in &src_files {
+            log::debug(format!("{} uses source {}", ctx.out_name, file.display()));
+            let object = object_path(&obj_dir, ctx.root_dir, file);
+            create_parent(&object)?;
 
-        program.arg(standard);
+            let mut program = self.unit_cmd(file, tests)?;
+            program.arg("-c").arg("-o").arg(&object);
+            if enable_dbg {
+                program.arg("-g");
+            } else if is_release {
+                program.arg("-O3");
+            }
+            compiled.push(graph.add(
+                NodeKind::Compile,
+                file.display().to_string(),
+                Action::Command(program),
+                after,
+            ));
+            objects.push(object);
+        }
 
+        let mut program = Command::new(self.cmd);
+        program
+            .args(objects)
+            .args(ctx.objects)
+            .arg("-o")
+            .arg(ctx.out_dir.join(ctx.out_name));
+        color_diagnostics(&mut program);
         self.link_lib(&mut program).context("Failed to link program to build executable")?;
 
-        program
-            .status()
-            .context("Failed to build executable")
-            .map(|_| ())
+        // Pre-built objects don't have to wait for anything
+        if compiled.is_empty() {
+            compiled.extend_from_slice(after);
+        }
+        let label = ctx.out_dir.join(ctx.out_name).display().to_string();
+        Ok(graph.add(NodeKind::Link, label, Action::Command(program), &compiled))
     }
 
-    pub fn build_lib(&self, ctx: CompileCtx<'c>) -> anyhow::Result<()> {
-        let standard = format!("-std={}", self.props.c_std);
-        let mut src_files = util::get_src_files(&ctx.root_dir.join("src"));
+    /// Source files that are put into the library, without its main file
+    pub fn lib_src_files(&self, ctx: &CompileCtx<'c>) -> HashSet<PathBuf> {
+        let mut src_files = self.src_files(ctx);
         src_files.remove(&ctx.root_dir.join("src").join(DEFAULT_LIB_EXCLUDE));
-        src_files.retain(|e| !ctx.excluded.contains(e));
+        src_files
+    }
+
+    /// Builds the sources into an archive, `cfg_flags` are the defines
+    /// and include directories of the project the sources belong to
+    pub fn build_lib(
+        &self,
+        graph: &mut BuildGraph,
+        ctx: CompileCtx<'c>,
+        cfg_flags: &[String],
+        after: &[NodeId],
+    ) -> anyhow::Result<Option<NodeId>> {
+        let standard = self.std_flag();
+        let mut src_files: Vec<PathBuf> = self.lib_src_files(&ctx).into_iter().collect();
+        src_files.sort();
         let mut out_names = Vec::new();
+        let mut compiled = Vec::new();
 
-        if src_files.is_empty() {
-            return Ok(());
+        if src_files.is_empty() && ctx.objects.is_empty() {
+            log::warn(format!("No source files to build {} from", ctx.out_name));
+            return Ok(None);
         }
 
-        // TODO: Sort all object files into directory
-
+        let obj_dir = obj_dir(&ctx);
         for file in src_files {
             let mut program = Command::new(self.cmd);
-            // TODO: Clean this up
-            let name = file.file_name().unwrap().to_string_lossy().to_string();
-            let out_path = ctx.out_dir.join(&name[..name.len() - 2]);
+            let out_path = object_path(&obj_dir, ctx.root_dir, &file);
+            create_parent(&out_path)?;
             program
                 .arg("-c")
+                .arg("-x")
+                .arg(SourceKind::from_path(&file).lang())
                 .arg(&file)
                 .arg("-o")
                 .arg(&out_path)
                 .arg("-w")
                 .arg("-DNOTESTS")
-                .arg(&standard);
-            program
-                .spawn()
-                .context(format!("Failed to compile src file: {}", &file.display()))?
-                .wait()
-                .context("Failed to wait for src file to compile")?;
+                .arg(&standard)
+                .args(cfg_flags);
+            color_diagnostics(&mut program);
+            self.include_deps(&mut program)?;
+            compiled.push(graph.add(
+                NodeKind::Compile,
+                file.display().to_string(),
+                Action::Command(program),
+                after,
+            ));
             out_names.push(out_path);
         }
+        // Archives can't be put into the library, they are linked by its users instead
+        out_names.extend(
+            ctx.objects
+                .iter()
+                .filter(|object| object.extension().is_some_and(|ext| ext == "o"))
+                .cloned(),
+        );
         let mut linker = Command::new("ar");
         linker
             .arg("rcs")
             .arg(ctx.out_dir.join(ctx.out_name))
             .args(out_names);
-        linker.spawn().context("Failed to link library")?;
+        // Pre-built objects don't have to wait for anything
+        if compiled.is_empty() {
+            compiled.extend_from_slice(after);
+        }
+        let label = ctx.out_dir.join(ctx.out_name).display().to_string();
+        Ok(Some(graph.add(NodeKind::Archive, label, Action::Command(linker), &compiled)))
+    }
+
+    /// Flags every translation unit of the project is compiled with
+    fn common_args(&self, cmd: &mut Command, tests: bool) -> anyhow::Result<()> {
+        if !tests {
+            cmd.arg("-DNOTESTS");
+        }
+
+        cmd.arg(self.std_flag());
+
+        cmd.args(self.cfg_flags(&|path| path.display().to_string()));
+
+        self.include_deps(cmd)?;
+
+        // Headers shipped with surtur, like surtur_bench.h
+        cmd.arg(format!("-I{}", global::include_dir()?.display()));
         Ok(())
     }
 
-    pub fn link_lib(&self, cmd: &mut Command) -> anyhow::Result<()> {
-        cmd.arg("-Lbuild/");
-        for dep in &self.dm.deps {
-            let name = dep.name()?;
-            cmd.arg(format!("-l:{name}/{name}.a"));
+    /// All translation units of the project, including
+    /// its binaries, examples and benchmarks
+    pub fn translation_units(&self, ctx: &CompileCtx<'c>) -> Vec<PathBuf> {
+        let mut src_files: Vec<PathBuf> = self.src_files(ctx).into_iter().collect();
+        src_files.extend(self.bins.values().cloned());
+        src_files.extend(self.examples.values().cloned());
+        src_files.extend(self.benches.values().cloned());
+        src_files.sort();
+        src_files
+    }
+
+    /// Command that compiles a single translation unit with the
+    /// project's flags, the caller decides what is produced
+    pub fn unit_cmd(&self, file: &Path, tests: bool) -> anyhow::Result<Command> {
+        let mut program = Command::new(self.cmd);
+        program
+            .arg("-x")
+            .arg(SourceKind::from_path(file).lang())
+            .arg(file);
+        color_diagnostics(&mut program);
+        self.common_args(&mut program, tests)?;
+        Ok(program)
+    }
+
+    pub fn is_clang(&self) -> bool {
+        Path::new(self.cmd)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().contains("clang"))
+    }
+
+    /// Checks every translation unit of the project for errors
+    /// without compiling or linking it and returns how many failed
+    pub fn check(&self, ctx: CompileCtx<'c>, tests: bool) -> anyhow::Result<usize> {
+        let src_files = self.translation_units(&ctx);
+
+        let mut cmds = Vec::new();
+        for file in &src_files {
+            let mut program = self.unit_cmd(file, tests)?;
+            program.arg("-fsyntax-only");
+            cmds.push(program);
         }
 
-        for lib in self.libs {
-            cmd.arg(format!("-l{lib}"));
+        log::status(
+            "Checking",
+            format!("{} ({} files)", ctx.out_name, src_files.len()),
+        );
+        util::exec_parallel(cmds)
+    }
+
+    /// Defines and include directories of the project, paths are turned into strings by `path`
+    pub fn cfg_flags(&self, path: &dyn Fn(&Path) -> String) -> Vec<String> {
+        cfg_flags(self.defines, self.include_dirs, path)
+    }
+
+    /// Makes the headers of all dependencies available
+    pub fn include_deps(&self, cmd: &mut Command) -> anyhow::Result<()> {
+        cmd.args(self.include_flags(&|path| path.display().to_string())?);
+        Ok(())
+    }
+
+    /// Include flags of the dependencies, paths are turned into strings by `path`
+    pub fn include_flags(&self, path: &dyn Fn(&Path) -> String) -> anyhow::Result<Vec<String>> {
+        let mut flags = Vec::new();
+        for pkg in &self.packages {
+            flags.push(format!("-I{}", path(&pkg.location.join("include"))));
         }
+        Ok(flags)
+    }
 
+    pub fn link_lib(&self, cmd: &mut Command) -> anyhow::Result<()> {
+        cmd.args(self.link_flags(&util::target_dir(), &|path| path.display().to_string())?);
         Ok(())
     }
 
-    pub fn build_deps(&self) -> anyhow::Result<()> {
-        for dep in &self.dm.deps {
-            let out_dir = self.proj_dir.join("build").join(dep.name()?);
+    /// Flags for linking the dependencies and libraries, paths are turned into strings by `path`
+    pub fn link_flags(
+        &self,
+        target_dir: &Path,
+        path: &dyn Fn(&Path) -> String,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut flags = vec![format!("-L{}", path(target_dir))];
+        // Archives are searched once, so packages go before the packages they depend on
+        for pkg in self.packages.iter().rev() {
+            let name = &pkg.name;
+            flags.push(format!("-l:{name}/{name}.a"));
+            for object in &pkg.cfg.objects {
+                if object.extension().is_some_and(|ext| ext == "a") {
+                    flags.push(path(object));
+                }
+            }
+        }
+
+        // Libraries of dependencies are linked by the project, archives don't carry them
+        let libs: BTreeSet<&String> = self
+            .libs
+            .iter()
+            .chain(self.packages.iter().flat_map(|pkg| &pkg.cfg.libraries))
+            .collect();
+        for lib in libs {
+            flags.push(format!("-l{lib}"));
+        }
+
+        Ok(flags)
+    }
+
+    /// Direct and transitive dependencies, packages come after their dependencies
+    pub fn packages(&self) -> &[Package] {
+        &self.packages
+    }
+
+    pub fn cmd(&self) -> &str {
+        self.cmd
+    }
+
+    pub fn std_flag(&self) -> String {
+        format!("-std={}", self.props.c_std)
+    }
+
+    /// Adds the steps that build the dependencies to the graph and returns
+    /// a node for every dependency. The caller has to hold the dependency lock
+    pub fn build_deps(&self, graph: &mut BuildGraph, after: &[NodeId]) -> anyhow::Result<Vec<NodeId>> {
+        let mut nodes: Vec<NodeId> = Vec::new();
+        for pkg in &self.packages {
+            let out_dir = util::target_dir().join(&pkg.name);
             if !out_dir.exists() {
                 fs::create_dir(&out_dir)?;
             }
-            let mut name = dep.name()?;
-            name.push_str(".a");
-            let cfg = dep.config()?;
+            let name = format!("{}.a", pkg.name);
+            let sources = pkg.cfg.active_sources();
             let ctx = CompileCtx {
                 out_dir: &out_dir,
                 out_name: &name,
-                root_dir: &dep.location()?,
-                excluded: &cfg.excluded,
+                root_dir: &pkg.location,
+                sources: &sources,
+                objects: &pkg.cfg.objects,
             };
-            self.build_lib(ctx)
+            let display = |path: &Path| path.display().to_string();
+            let cfg_flags = cfg_flags(&pkg.cfg.defines, &pkg.cfg.include_dirs, &display);
+            let archive = self
+                .build_lib(graph, ctx, &cfg_flags, after)
                 .context(format!("Failed to build library {}", name))?;
+            // A package is only done once the packages it depends on are
+            let mut deps: Vec<NodeId> = archive.into_iter().collect();
+            deps.extend(pkg.deps.iter().map(|idx| nodes[*idx]));
+            nodes.push(graph.add(NodeKind::Dependency, &pkg.name, Action::None, &deps));
+        }
+        Ok(nodes)
+    }
+}
+
+/// Flags for the defines and include directories of a config
+pub fn cfg_flags(
+    defines: &[String],
+    include_dirs: &[PathBuf],
+    path: &dyn Fn(&Path) -> String,
+) -> Vec<String> {
+    let mut flags: Vec<String> = defines.iter().map(|define| format!("-D{define}")).collect();
+    flags.extend(include_dirs.iter().map(|dir| format!("-I{}", path(dir))));
+    flags
+}
+
+/// Directory the objects of the context's output are compiled into
+fn obj_dir(ctx: &CompileCtx) -> PathBuf {
+    ctx.out_dir.join(OBJ_DIR).join(ctx.out_name)
+}
+
+/// Object of a source file, named after the source's path relative to the project's
+/// `src` directory, e.g. `a/util.c.o`. The extension is kept so lib.c and lib.S don't
+/// share an object. Sources outside of the project are told apart by a hash of their path
+pub fn object_path(obj_dir: &Path, root_dir: &Path, file: &Path) -> PathBuf {
+    let rel = file
+        .strip_prefix(root_dir.join("src"))
+        .or_else(|_| file.strip_prefix(root_dir))
+        .ok()
+        .filter(|rel| rel.components().all(|comp| matches!(comp, Component::Normal(_))));
+    let mut object = match rel {
+        Some(rel) => obj_dir.join(rel),
+        None => {
+            let hash = format!("{:x}", Sha256::digest(file.to_string_lossy().as_bytes()));
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            obj_dir.join(format!("{}-{}", &hash[..16], name))
         }
-        Ok(())
     }
+    .into_os_string();
+    object.push(".o");
+    object.into()
+}
 
+fn create_parent(path: &Path) -> anyhow::Result<()> {
+    let dir = path.parent().context("Object file has no directory")?;
+    fs::create_dir_all(dir).context(format!("Failed to create directory {}", dir.display()))
+}
+
+/// Diagnostics of the graph's commands are captured, so the
+/// compiler has to be told to keep its colors
+fn color_diagnostics(cmd: &mut Command) {
+    if io::stderr().is_terminal() {
+        cmd.arg("-fdiagnostics-color=always");
+    }
 }
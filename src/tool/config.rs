@@ -1,6 +1,8 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
+    env,
     fmt::Display,
+    fs,
     path::{Path, PathBuf},
 };
 
@@ -10,12 +12,13 @@ use anyhow::{bail, Context};
 /// related to the project's configuration
 use mlua::{Lua, Table, Value};
 
-use crate::util::{files::FileHandler, DEFAULT_COMPILER};
+use crate::util::{self, files::FileHandler, glob, DEFAULT_COMPILER, DEFAULT_TARGET_DIR};
 
 use super::{
     compiler::Standard,
-    deps::{DepManager, Dependency},
+    deps::{DepManager, Dependency, GitRef},
     scripts::ScriptManager,
+    semver::VersionReq,
 };
 
 // TODO: Seperate tables from rest of the struct so it represents the actual config file
@@ -24,9 +27,32 @@ pub struct Config {
     pub props: Properties,
     pub deps: DepManager,
     pub entry: PathBuf,
+    /// Source files of the project without the excluded ones,
+    /// files of conditional groups are included as well
+    pub sources: HashSet<PathBuf>,
     pub excluded: HashSet<PathBuf>,
+    /// Source files that are only compiled under certain conditions
+    pub source_groups: Vec<SourceGroup>,
+    /// Enabled features of the project
+    pub features: HashSet<String>,
+    /// Whether dev dependencies are used, set when building tests, examples and benchmarks
+    pub dev: bool,
     pub scripts: Option<ScriptManager>,
     pub libraries: HashSet<String>,
+    /// Additional executables, mapped from their name to their entry file
+    pub binaries: HashMap<String, PathBuf>,
+    /// Example programs from the examples directory, mapped from their name to their file
+    pub examples: HashMap<String, PathBuf>,
+    /// Benchmarks from the benches directory, mapped from their name to their file
+    pub benches: HashMap<String, PathBuf>,
+    /// Directory all build outputs go to
+    pub target_dir: PathBuf,
+    /// Pre-built object files and archives that are linked as they are
+    pub objects: Vec<PathBuf>,
+    /// Preprocessor definitions as `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    /// Additional directories searched for headers
+    pub include_dirs: Vec<PathBuf>,
 }
 
 pub struct Properties {
@@ -34,6 +60,67 @@ pub struct Properties {
     pub proj_version: String,
     pub proj_type: ProjType,
     pub compiler: String,
+    /// Target triple for cross compilation, e.g. x86_64-w64-mingw32
+    pub target: Option<String>,
+}
+
+/// Operating system and architecture the project is built for
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Platform {
+    pub os: String,
+    pub arch: String,
+}
+
+impl Platform {
+    pub fn host() -> Self {
+        Self {
+            os: env::consts::OS.into(),
+            arch: env::consts::ARCH.into(),
+        }
+    }
+
+    pub fn from_triple(triple: &str) -> Self {
+        let arch = triple.split('-').next().unwrap_or(triple).to_string();
+        let os = if triple.contains("mingw") || triple.contains("windows") {
+            "windows"
+        } else if triple.contains("darwin") || triple.contains("apple") {
+            "macos"
+        } else if triple.contains("freebsd") {
+            "freebsd"
+        } else if triple.contains("linux") {
+            "linux"
+        } else {
+            "none"
+        };
+        Self {
+            os: os.into(),
+            arch,
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.arch, self.os)
+    }
+}
+
+/// Source files that are only compiled if all of the
+/// specified conditions match
+#[derive(Debug)]
+pub struct SourceGroup {
+    pub os: Option<String>,
+    pub arch: Option<String>,
+    pub feature: Option<String>,
+    pub files: HashSet<PathBuf>,
+}
+
+impl SourceGroup {
+    pub fn is_active(&self, platform: &Platform, features: &HashSet<String>) -> bool {
+        self.os.as_ref().is_none_or(|os| *os == platform.os)
+            && self.arch.as_ref().is_none_or(|arch| *arch == platform.arch)
+            && self.feature.as_ref().is_none_or(|feature| features.contains(feature))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
@@ -43,6 +130,7 @@ pub enum ProjType {
 }
 
 impl ProjType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(c_std: &str) -> Option<ProjType> {
         match c_std {
             "lib" => Some(ProjType::Lib),
@@ -65,6 +153,7 @@ impl Config {
     pub fn parse(root_dir: &Path, file: FileHandler) -> anyhow::Result<Self> {
         let mut dependencies = HashSet::new();
         let mut libraries = HashSet::new();
+        let mut binaries = HashMap::new();
         let mut excluded: HashSet<PathBuf> = HashSet::new();
 
         let lua = Lua::new();
@@ -74,7 +163,7 @@ impl Config {
 
         lua.load(&file.file_content)
             .exec()
-            .expect("Failed to load context");
+            .context(format!("Failed to load {}", file.file_path.display()))?;
 
         let name: String = lua
             .globals()
@@ -85,70 +174,152 @@ impl Config {
         let props_table: Table = lua
             .globals()
             .get("Props")
-            .expect("Failed to get properties");
+            .context("Failed to get properties even though they are required")?;
 
         // dependencies
         let dep_table: Option<Table> = lua.globals().get("Dependencies").ok();
 
+        let dev_dep_table: Option<Table> = lua.globals().get("DevDependencies").ok();
+
         let scripts_table: Option<Table> = lua.globals().get("Scripts").ok();
 
         let libraries_table: Option<Table> = lua.globals().get("Libraries").ok();
 
         let excluded_table: Option<Table> = lua.globals().get("Exclude").ok();
 
+        let binaries_table: Option<Table> = lua.globals().get("Binaries").ok();
+
+        let objects_table: Option<Table> = lua.globals().get("Objects").ok();
+
+        let sources_table: Option<Table> = lua.globals().get("Sources").ok();
+
+        let conditional_table: Option<Table> = lua.globals().get("Conditional").ok();
+
+        let features_table: Option<Table> = lua.globals().get("Features").ok();
+
+        let defines_table: Option<Table> = lua.globals().get("Defines").ok();
+
+        let include_dirs_table: Option<Table> = lua.globals().get("IncludeDirs").ok();
+
         let mut props = Properties {
             c_std: Standard::C23,
             proj_version: String::new(),
             proj_type: ProjType::Bin,
             compiler: String::from(DEFAULT_COMPILER),
+            target: None,
         };
 
         for pair in props_table.pairs::<String, String>() {
-            let (key, val) = pair.expect("Failed to get pair");
+            let (key, val) = pair.context("Failed to get property")?;
             match key.to_lowercase().as_str() {
                 "std" => props.c_std = Standard::from_str(&val)
                     .context(format!("`{}` is not a valid value for the projects C Standard", val))?,
                 "version" => props.proj_version = val,
                 "compiler" => props.compiler = val,
+                "target" => props.target = Some(val),
                 "type" => props.proj_type = ProjType::from_str(&val)
                     .context(format!("`{}` is not a valid value for the projects type. Valid types are: `lib` and `bin`", val))?,
                 key => bail!("invalid version entry: {}", key),
             }
         }
 
+        let src_dir = root_dir.join("src");
+
+        // Without a Sources table every source file in src is compiled
+        let mut sources = match sources_table {
+            Some(table) => {
+                let mut sources = HashSet::new();
+                for pattern in table.sequence_values::<String>() {
+                    let pattern = pattern.context("Failed to get source pattern")?;
+                    sources.extend(
+                        glob::find(root_dir, &pattern)
+                            .into_iter()
+                            .filter(|file| util::is_src_file(file)),
+                    );
+                }
+                sources
+            }
+            None if src_dir.exists() => util::get_src_files(&src_dir),
+            None => HashSet::new(),
+        };
+
+        // Exclude patterns are relative to src, or to the project if they don't match there
         if let Some(table) = excluded_table {
-            for elem in table.sequence_values::<String>().flatten() {
-                excluded.insert(root_dir.join("src").join(elem));
+            let patterns: Vec<String> = table.sequence_values::<String>().flatten().collect();
+            for file in &sources {
+                let is_excluded = patterns.iter().any(|pattern| {
+                    glob::relative(file, &src_dir).is_some_and(|rel| glob::matches(pattern, &rel))
+                        || glob::relative(file, root_dir)
+                            .is_some_and(|rel| glob::matches(pattern, &rel))
+                });
+                if is_excluded {
+                    excluded.insert(file.clone());
+                }
+            }
+            // Excluded files that don't exist yet are kept for backwards compatibility
+            for pattern in patterns.iter().filter(|pattern| !glob::is_glob(pattern)) {
+                excluded.insert(src_dir.join(pattern));
+            }
+        }
+        sources.retain(|file| !excluded.contains(file));
+
+        let mut source_groups = Vec::new();
+
+        if let Some(table) = conditional_table {
+            for group in table.sequence_values::<Table>() {
+                let group = group.context("Failed to get conditional source group")?;
+                let patterns: Table = group
+                    .get("sources")
+                    .context("Conditional source groups require a `sources` table")?;
+                let mut files = HashSet::new();
+                for pattern in patterns.sequence_values::<String>() {
+                    let pattern = pattern.context("Failed to get source pattern")?;
+                    files.extend(
+                        glob::find(root_dir, &pattern)
+                            .into_iter()
+                            .filter(|file| util::is_src_file(file) && !excluded.contains(file)),
+                    );
+                }
+                sources.extend(files.iter().cloned());
+                source_groups.push(SourceGroup {
+                    os: group.get("os")?,
+                    arch: group.get("arch")?,
+                    feature: group.get("feature")?,
+                    files,
+                });
+            }
+        }
+
+        let mut features = HashSet::new();
+
+        if let Some(table) = features_table {
+            for feature in table.sequence_values::<String>() {
+                features.insert(feature.context("Failed to get feature")?);
             }
         }
 
         // Iterating over dependencies
         if let Some(deps) = dep_table {
             for dep in deps.sequence_values::<Table>() {
-                let mut version = 0.0;
-                let mut origin = String::new();
                 let table = dep.context("Failed to get dependency table")?;
-                for pair in table.sequence_values::<Value>() {
-                    match pair.context("Failed to get dependency pair")? {
-                        Value::Integer(int_value) => {
-                            version = int_value as f64;
-                        }
-                        Value::String(string_value) => {
-                            origin = string_value.to_string_lossy().to_string();
-                        }
-                        Value::Number(num_value) => {
-                            version = num_value;
-                        }
-                        val => {
-                            bail!("Invalid value in dependency table, value: {val:?}")
-                        }
-                    }
-                }
-                let dependency = Dependency::new(&origin, &version.to_string());
-                dependencies.insert(dependency);
+                dependencies.insert(Self::parse_dep(root_dir, table)?);
+            }
+        }
+
+        if let Some(deps) = dev_dep_table {
+            for dep in deps.sequence_values::<Table>() {
+                let table = dep.context("Failed to get dev dependency table")?;
+                let mut dep = Self::parse_dep(root_dir, table)?;
+                dep.dev = true;
+                dependencies.insert(dep);
             }
         }
 
+        let target_dir = match lua.globals().get::<Option<String>>("TargetDir")? {
+            Some(dir) => root_dir.join(dir),
+            None => root_dir.join(DEFAULT_TARGET_DIR),
+        };
+
         let entry: String = lua.globals().get("Entry").unwrap_or(
             match props.proj_type {
                 ProjType::Lib => "lib.c",
@@ -166,13 +337,13 @@ impl Config {
                     "pre" => {
                         pre_scripts = val
                             .sequence_values::<String>()
-                            .map(|val| PathBuf::from(val.unwrap()))
+                            .map(|val| root_dir.join(val.unwrap()))
                             .collect()
                     }
                     "post" => {
                         post_scripts = val
                             .sequence_values::<String>()
-                            .map(|val| PathBuf::from(val.unwrap()))
+                            .map(|val| root_dir.join(val.unwrap()))
                             .collect()
                     }
                     key => bail!("Found invalid key: {key}"),
@@ -193,14 +364,180 @@ impl Config {
             }
         }
 
+        let mut objects = Vec::new();
+
+        if let Some(table) = objects_table {
+            for object in table.sequence_values::<String>() {
+                let object = root_dir.join(object.context("Failed to get object")?);
+                match object.extension().and_then(|ext| ext.to_str()) {
+                    Some("o" | "a") => objects.push(object),
+                    _ => bail!(
+                        "`{}` is not a valid object, only .o and .a files can be linked",
+                        object.display()
+                    ),
+                }
+            }
+        }
+
+        let mut defines = Vec::new();
+
+        if let Some(table) = defines_table {
+            for define in table.sequence_values::<String>() {
+                defines.push(define.context("Failed to get define")?);
+            }
+        }
+
+        let mut include_dirs = Vec::new();
+
+        if let Some(table) = include_dirs_table {
+            for dir in table.sequence_values::<String>() {
+                include_dirs.push(root_dir.join(dir.context("Failed to get include directory")?));
+            }
+        }
+
+        // Every c file in src/bin is its own binary
+        binaries.extend(Self::entry_files(&root_dir.join("src").join("bin"))?);
+
+        if let Some(table) = binaries_table {
+            for pair in table.pairs::<String, String>() {
+                let (name, path) = pair.context("Failed to get binary entry")?;
+                binaries.insert(name, root_dir.join(path));
+            }
+        }
+
         Ok(Self {
             name,
             props,
             deps: DepManager::new(dependencies),
             entry: entry.into(),
+            sources,
             excluded,
+            source_groups,
+            features,
+            dev: false,
             scripts,
             libraries,
+            binaries,
+            examples: Self::entry_files(&root_dir.join("examples"))?,
+            benches: Self::entry_files(&root_dir.join("benches"))?,
+            target_dir,
+            objects,
+            defines,
+            include_dirs,
         })
     }
+
+    /// Platform the project is built for
+    pub fn platform(&self) -> Platform {
+        match &self.props.target {
+            Some(triple) => Platform::from_triple(triple),
+            None => Platform::host(),
+        }
+    }
+
+    /// Name of the target the project is built for, the
+    /// configured triple or the host's platform
+    pub fn target(&self) -> String {
+        match &self.props.target {
+            Some(triple) => triple.clone(),
+            None => Platform::host().to_string(),
+        }
+    }
+
+    /// Source files that are compiled for the platform
+    /// and features the project is built with
+    pub fn active_sources(&self) -> HashSet<PathBuf> {
+        let platform = self.platform();
+        let mut sources = self.sources.clone();
+        for group in &self.source_groups {
+            if !group.is_active(&platform, &self.features) {
+                sources.retain(|file| !group.files.contains(file));
+            }
+        }
+        sources
+    }
+
+    /// Maps every c file in the directory to its name
+    /// without the file extension
+    fn entry_files(dir: &Path) -> anyhow::Result<HashMap<String, PathBuf>> {
+        let mut files = HashMap::new();
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "c") {
+                    let name = path
+                        .file_stem()
+                        .context(format!("Invalid entry file: {}", path.display()))?
+                        .to_string_lossy()
+                        .to_string();
+                    files.insert(name, path);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Parses an entry of the `Dependencies` table, either keyed like
+    /// `{ git = "...", tag = "v1.0" }` or positional like `{ "url", "^1.2" }`
+    pub fn parse_dep(root_dir: &Path, table: Table) -> anyhow::Result<Dependency> {
+        let optional = table.get::<Option<bool>>("optional")?.unwrap_or(false);
+        let mut dep = Self::parse_source(root_dir, table)?;
+        dep.optional = optional;
+        Ok(dep)
+    }
+
+    fn parse_source(root_dir: &Path, table: Table) -> anyhow::Result<Dependency> {
+        if let Some(path) = table.get::<Option<String>>("path")? {
+            return Ok(Dependency::from_path(root_dir, &path));
+        }
+
+        if let Some(url) = table.get::<Option<String>>("git")? {
+            let mut refs = Vec::new();
+            for key in ["version", "tag", "rev", "branch"] {
+                if let Some(value) = table.get::<Option<String>>(key)? {
+                    refs.push((key, value));
+                }
+            }
+            if refs.len() > 1 {
+                bail!("Dependency {} can only have one of `version`, `tag`, `rev` and `branch`", url);
+            }
+            let reference = match refs.pop() {
+                None => GitRef::DefaultBranch,
+                Some(("version", req)) => {
+                    VersionReq::parse(&req).context(format!("Invalid version of dependency {}", url))?;
+                    GitRef::Version(req)
+                }
+                Some(("tag", tag)) => GitRef::Tag(tag),
+                Some(("rev", rev)) => GitRef::Rev(rev),
+                Some((_, branch)) => GitRef::Branch(branch),
+            };
+            return Ok(Dependency::git(&url, reference));
+        }
+
+        if let Some(url) = table.get::<Option<String>>("url")? {
+            let sha256 = table
+                .get::<Option<String>>("sha256")?
+                .context(format!("Dependency {} requires a `sha256` checksum", url))?;
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Invalid sha256 checksum of dependency {}", url);
+            }
+            return Ok(Dependency::tarball(&url, &sha256));
+        }
+
+        // Plain numbers like 0.1 are read as ^0.1
+        let mut pairs = table.sequence_values::<Value>();
+        let origin = match pairs.next().transpose()? {
+            Some(Value::String(origin)) => origin.to_string_lossy().to_string(),
+            val => bail!("Invalid origin in dependency table, value: {val:?}"),
+        };
+        let version = match pairs.next().transpose()? {
+            Some(Value::String(version)) => version.to_string_lossy().to_string(),
+            Some(Value::Integer(version)) => version.to_string(),
+            Some(Value::Number(version)) => version.to_string(),
+            None => "*".to_string(),
+            val => bail!("Invalid version of {origin} in dependency table, value: {val:?}"),
+        };
+        VersionReq::parse(&version).context(format!("Invalid version of dependency {}", origin))?;
+        Ok(Dependency::new(&origin, &version))
+    }
 }
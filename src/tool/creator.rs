@@ -14,6 +14,8 @@ use git2::{Repository, RepositoryInitOptions};
 
 use crate::util::{self, DEFAULT_COMPILER};
 
+use super::formatter::CLANG_FORMAT_FILE;
+
 #[derive(Debug)]
 pub struct Project<'p> {
     pub root_dir: &'p PathBuf,
@@ -57,6 +59,15 @@ int square(int number);
 
 const GITIGNORE_LAYOUT: &str = "build/\n";
 
+const CLANG_FORMAT_LAYOUT: &str = r#"BasedOnStyle: LLVM
+IndentWidth: 4
+ColumnLimit: 100
+AllowShortFunctionsOnASingleLine: None
+AllowShortIfStatementsOnASingleLine: Never
+PointerAlignment: Right
+SortIncludes: false
+"#;
+
 impl<'p> Project<'p> {
     pub fn new(root_dir: &'p PathBuf) -> Self {
         let name = root_dir.file_name().unwrap().to_string_lossy().to_string();
@@ -76,6 +87,9 @@ impl<'p> Project<'p> {
         // .gitignore file
         Self::create_gitignore(self.root_dir)?;
 
+        // .clang-format file
+        Self::create_clang_format(self.root_dir)?;
+
         // Cfg file
         Self::create_cfg_file(self.root_dir, &self.name, is_lib)?;
 
@@ -128,7 +142,7 @@ impl<'p> Project<'p> {
                 "{}",
                 "\n-- external dependenciess\n",
                 "Dependencies = {{\n",
-                "    -- {{ \"https://github.com/Surtur-Team/surtests\", 0.1 }}\n",
+                "    -- {{ \"https://github.com/Surtur-Team/surtests\", \"^0.1\" }}\n",
                 "}}\n"
             ),
             name,
@@ -198,4 +212,13 @@ impl<'p> Project<'p> {
             .write_all(GITIGNORE_LAYOUT.as_bytes())
             .context("Failed to write gitignore content to .gitignore")
     }
+
+    pub fn create_clang_format(root_dir: &Path) -> anyhow::Result<()> {
+        let mut clang_format_file = File::create(root_dir.join(CLANG_FORMAT_FILE))
+            .context("Failed to create .clang-format file")?;
+
+        clang_format_file
+            .write_all(CLANG_FORMAT_LAYOUT.as_bytes())
+            .context("Failed to write style to .clang-format")
+    }
 }
@@ -8,23 +8,408 @@
 /// Individula dependencies are in the Dependency
 /// struct and store basic information about the
 /// specific dependency
-use std::{collections::HashSet, env, error::Error, fmt::Display, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::bail;
+use anyhow::{bail, Context};
+use git2::Repository;
+use sha2::{Digest, Sha256};
 
-use crate::{global, util::files::FileHandler};
+use crate::{
+    global,
+    util::{self, files::FileHandler, log, LOCKFILE_NAME},
+};
 
-use super::config::Config;
+use super::{
+    config::Config,
+    lockfile::{LockedPackage, Lockfile},
+    semver::{Version, VersionReq},
+};
 
 #[derive(Debug, Default)]
 pub struct DepManager {
     pub deps: HashSet<Dependency>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Dependency {
-    pub _version: String,
-    pub origin: String,
+    pub source: DepSource,
+    /// Only used by tests, examples and benchmarks, from the `DevDependencies` table
+    pub dev: bool,
+    /// Only used if the feature with the dependency's name is enabled
+    pub optional: bool,
+}
+
+/// Where a dependency comes from
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum DepSource {
+    /// A git repository that gets cloned into the global dependency directory
+    Git { url: String, reference: GitRef },
+    /// A project on the local file system, e.g. another workspace member
+    Path(PathBuf),
+    /// An archive that gets downloaded and is checked against its checksum
+    Tarball { url: String, sha256: String },
+}
+
+/// What of a git repository is checked out
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum GitRef {
+    /// Highest version tag that matches the requirement
+    Version(String),
+    Tag(String),
+    Rev(String),
+    Branch(String),
+    DefaultBranch,
+}
+
+impl GitRef {
+    /// The reference as it is stored in the lockfile and shown to the user
+    pub fn requested(&self) -> String {
+        match self {
+            GitRef::Version(req) => req.clone(),
+            GitRef::Tag(tag) => format!("tag {}", tag),
+            GitRef::Rev(rev) => format!("rev {}", rev),
+            GitRef::Branch(branch) => format!("branch {}", branch),
+            GitRef::DefaultBranch => "default branch".into(),
+        }
+    }
+}
+
+impl Display for DepSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DepSource::Git { url, .. } | DepSource::Tarball { url, .. } => f.write_str(url),
+            DepSource::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A dependency of the resolved dependency graph, direct or transitive
+pub struct Package {
+    pub name: String,
+    pub dep: Dependency,
+    pub cfg: Config,
+    /// Where the package is checked out
+    pub location: PathBuf,
+    /// Tag and commit of git dependencies
+    pub locked: Option<LockedPackage>,
+    /// Indices of the packages it depends on, they always come before it
+    pub deps: Vec<usize>,
+}
+
+impl Package {
+    /// Whether the package is good enough for `dep`, the same package required by someone else
+    fn satisfies(&self, dep: &Dependency) -> anyhow::Result<bool> {
+        match (&self.dep.source, &dep.source, &self.locked) {
+            (DepSource::Git { url, .. }, DepSource::Git { url: other_url, reference }, Some(locked)) => {
+                if url != other_url {
+                    return Ok(false);
+                }
+                Ok(match reference {
+                    GitRef::Version(req) => {
+                        let req = VersionReq::parse(req)?;
+                        Version::parse(&locked.tag).is_ok_and(|version| req.matches(&version))
+                    }
+                    GitRef::Rev(rev) => locked.commit.starts_with(rev.as_str()),
+                    reference => reference.requested() == locked.version,
+                })
+            }
+            (DepSource::Path(path), DepSource::Path(other_path), _) => {
+                Ok(path.canonicalize().ok() == other_path.canonicalize().ok())
+            }
+            (DepSource::Tarball { sha256, .. }, DepSource::Tarball { sha256: other_sha256, .. }, _) => {
+                Ok(sha256.eq_ignore_ascii_case(other_sha256))
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Which git dependencies get a new version instead of the locked one
+#[derive(Debug, PartialEq, Eq)]
+pub enum Update {
+    /// Builds only use the lockfile
+    None,
+    All,
+    Package(String),
+}
+
+/// Walks the dependency graph depth first, so every
+/// package is added after the packages it depends on
+struct Resolver {
+    lock: Lockfile,
+    /// Resolves dev and optional dependencies of the project as well
+    everything: bool,
+    update: Update,
+    /// Clones the repositories of updated packages again
+    forced: bool,
+    packages: Vec<Package>,
+    /// Index of every resolved package by name
+    resolved: HashMap<String, usize>,
+    /// Who required every package first
+    required_by: HashMap<String, String>,
+    /// Packages that are being resolved, used to find cycles
+    path: Vec<String>,
+}
+
+impl Resolver {
+    fn new(lock: Lockfile, update: Update, forced: bool, everything: bool) -> Self {
+        Self {
+            lock,
+            everything,
+            update,
+            forced,
+            packages: Vec::new(),
+            resolved: HashMap::new(),
+            required_by: HashMap::new(),
+            path: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, dep: &Dependency, required_by: &str) -> anyhow::Result<usize> {
+        let name = dep.name()?;
+        if let Some(pos) = self.path.iter().position(|pkg| *pkg == name) {
+            let mut cycle = self.path[pos..].to_vec();
+            cycle.push(name);
+            bail!("Dependency cycle: {}", cycle.join(" -> "));
+        }
+        if let Some(idx) = self.resolved.get(&name) {
+            // The version that was picked first has to work for everyone
+            let first = &self.packages[*idx];
+            if !first.satisfies(dep)? {
+                let resolved = match &first.locked {
+                    Some(locked) => format!(" and got {}", locked.revision()),
+                    None => String::new(),
+                };
+                bail!(
+                    "Conflicting versions of {}: {} requires {}{}, {} requires {}",
+                    name,
+                    self.required_by[&name],
+                    first.dep.requirement(),
+                    resolved,
+                    required_by,
+                    dep.requirement()
+                );
+            }
+            return Ok(*idx);
+        }
+        self.required_by.insert(name.clone(), required_by.to_string());
+
+        let (location, locked) = match &dep.source {
+            DepSource::Git { url, reference } => {
+                let locked = self.pin(url, reference, &name, required_by)?;
+                (checkout_path(&name, &locked)?, Some(locked))
+            }
+            DepSource::Path(path) => (path.clone(), None),
+            // The checksum already pins tarballs, they don't need to be locked
+            DepSource::Tarball { url, sha256 } => {
+                let path = global::deps_dir()?.join(format!(
+                    "{}-{}",
+                    global::store_name(&name, url),
+                    &sha256[..sha256.len().min(12)]
+                ));
+                global::fetch_tarball(url, sha256, &path)?;
+                (path, None)
+            }
+        };
+        let cfg = read_config(&location)
+            .context(format!("Failed to read project of dependency {}", name))?;
+
+        self.path.push(name.clone());
+        let mut deps = Vec::new();
+        for child in sorted(&cfg.deps.deps) {
+            // Dev dependencies of dependencies are never built
+            if !self.uses(child, &cfg.features, false)? {
+                continue;
+            }
+            deps.push(self.visit(child, &name)?);
+        }
+        self.path.pop();
+
+        let idx = self.packages.len();
+        self.resolved.insert(name.clone(), idx);
+        self.packages.push(Package {
+            name,
+            dep: dep.clone(),
+            cfg,
+            location,
+            locked,
+            deps,
+        });
+        Ok(idx)
+    }
+
+    /// Whether the dependency is used with the enabled features
+    fn uses(&self, dep: &Dependency, features: &HashSet<String>, dev: bool) -> anyhow::Result<bool> {
+        if dep.dev && !dev {
+            return Ok(false);
+        }
+        Ok(!dep.optional || self.everything || features.contains(&dep.name()?))
+    }
+
+    /// Tag and commit of a git dependency, taken from the lockfile or from
+    /// the repository if it gets updated. Makes sure they are checked out
+    fn pin(&self, url: &str, reference: &GitRef, name: &str, required_by: &str) -> anyhow::Result<LockedPackage> {
+        let requested = reference.requested();
+        let locked = self
+            .lock
+            .find(name)
+            .filter(|locked| locked.source == url && locked.version == requested);
+        let updates = match &self.update {
+            Update::None => false,
+            Update::All => true,
+            Update::Package(pkg) => pkg == name,
+        };
+        let mut locked = match locked {
+            Some(locked) if !updates => locked.clone(),
+            None if self.update == Update::None => bail!(
+                "{} {} of {} is not in {}, run `surtur update`",
+                name,
+                requested,
+                required_by,
+                LOCKFILE_NAME
+            ),
+            _ => {
+                global::fetch_dep(url, name, self.forced)?;
+                let tag = match reference {
+                    GitRef::Version(req) => newest_tag(url, name, req)?,
+                    GitRef::Tag(tag) => tag.clone(),
+                    _ => String::new(),
+                };
+                let rev = match reference {
+                    GitRef::Rev(rev) => rev.clone(),
+                    GitRef::Branch(branch) => format!("refs/heads/{}", branch),
+                    GitRef::DefaultBranch => "HEAD".into(),
+                    GitRef::Version(_) | GitRef::Tag(_) => format!("refs/tags/{}", tag),
+                };
+                LockedPackage {
+                    name: name.to_string(),
+                    source: url.to_string(),
+                    version: requested,
+                    tag,
+                    commit: global::resolve_rev(url, name, &rev)?,
+                    checksum: String::new(),
+                }
+            }
+        };
+
+        let path = checkout_path(name, &locked)?;
+        // Held until the checkout is verified, so other builds can't replace or remove it meanwhile
+        let _lock = global::lock_deps(false)?;
+        let fresh = global::checkout_dep(url, name, &locked.commit, &path, self.forced && updates)?;
+        if locked.checksum.is_empty() {
+            locked.checksum = checksum(&path)?;
+        } else if fresh && checksum(&path)? != locked.checksum {
+            // Otherwise the next build would take the checkout as it is
+            fs::remove_dir_all(&path)?;
+            bail!(
+                "Files of {} {} don't match the checksum in {}, run `surtur update -p {}` if the change is expected",
+                name,
+                locked.revision(),
+                LOCKFILE_NAME,
+                name
+            );
+        }
+        Ok(locked)
+    }
+}
+
+/// Every version, tag or commit of a git dependency is checked out into its own directory
+fn checkout_path(name: &str, locked: &LockedPackage) -> anyhow::Result<PathBuf> {
+    let id = match Version::parse(&locked.tag) {
+        Ok(version) => version.to_string(),
+        Err(_) => locked.revision().replace('/', "-"),
+    };
+    Ok(global::deps_dir()?.join(format!("{}-{}", global::store_name(name, &locked.source), id)))
+}
+
+/// Highest version tag of the dependency's repository that matches
+/// the requirement, tags can be prefixed with a `v`
+fn newest_tag(url: &str, name: &str, req: &str) -> anyhow::Result<String> {
+    let version_req = VersionReq::parse(req)?;
+    let versions = versions(url, name)?;
+    let available: Vec<String> = versions.iter().map(|(version, _)| version.to_string()).collect();
+    match versions.into_iter().rev().find(|(version, _)| version_req.matches(version)) {
+        Some((_, tag)) => Ok(tag),
+        None if available.is_empty() => {
+            bail!("No version of {} matches {}, the repository has no version tags", name, req)
+        }
+        None => bail!(
+            "No version of {} matches {}, available versions: {}",
+            name,
+            req,
+            available.join(", ")
+        ),
+    }
+}
+
+/// Version tags of the dependency's downloaded repository, sorted by version
+fn versions(url: &str, name: &str) -> anyhow::Result<Vec<(Version, String)>> {
+    let repo = Repository::open_bare(global::dep_mirror(url, name)?)
+        .context(format!("Dependency {} is not downloaded, run `surtur update`", name))?;
+    let mut versions: Vec<(Version, String)> = repo
+        .tag_names(None)?
+        .iter()
+        .flatten()
+        .filter_map(|tag| Some((Version::parse(tag).ok()?, tag.to_string())))
+        .collect();
+    versions.sort();
+    Ok(versions)
+}
+
+/// Newest release of a git dependency, `None` if its repository has no version tags
+pub fn newest_version(url: &str, name: &str) -> anyhow::Result<Option<Version>> {
+    global::fetch_dep(url, name, false)?;
+    Ok(versions(url, name)?
+        .into_iter()
+        .map(|(version, _)| version)
+        .rfind(|version| version.pre.is_empty()))
+}
+
+fn read_config(location: &Path) -> anyhow::Result<Config> {
+    let cfg_path = location.join("project.lua");
+    let file = FileHandler::new(&cfg_path)?;
+    Config::parse(location, file)
+}
+
+/// Checksum of all files of a checked out dependency, without its git directory
+fn checksum(dir: &Path) -> anyhow::Result<String> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir).context(format!("Failed to read {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if path.file_name().is_some_and(|name| name != ".git") {
+                    dirs.push(path);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        // Paths are hashed as well, so renaming a file changes the checksum
+        hasher.update(file.strip_prefix(dir)?.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(&file)?);
+        hasher.update([0]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Dependencies in a stable order, so commands don't change between runs
+pub fn sorted(deps: &HashSet<Dependency>) -> Vec<&Dependency> {
+    let mut deps: Vec<&Dependency> = deps.iter().collect();
+    deps.sort_by_key(|dep| dep.requirement());
+    deps
 }
 
 impl DepManager {
@@ -32,51 +417,153 @@ impl DepManager {
         Self { deps: dependencies }
     }
 
-    /// Downloads the dependency into your projects depndency directoy
-    pub fn download_deps(&self, forced: bool) -> anyhow::Result<()> {
-        for dep in &self.deps {
-            global::download_dep(dep, forced)?;
+    /// Resolves all direct and transitive dependencies that are used with the
+    /// features, with the versions of the lockfile. `dev` includes dev dependencies.
+    /// Packages come after the packages they depend on, a package is only resolved once
+    pub fn resolve(&self, features: &HashSet<String>, dev: bool) -> anyhow::Result<Vec<Package>> {
+        let lock = Lockfile::load(&util::lockfile())?;
+        self.resolve_with(Resolver::new(lock, Update::None, false, false), features, dev)
+    }
+
+    fn resolve_with(
+        &self,
+        mut resolver: Resolver,
+        features: &HashSet<String>,
+        dev: bool,
+    ) -> anyhow::Result<Vec<Package>> {
+        for dep in sorted(&self.deps) {
+            if resolver.uses(dep, features, dev)? {
+                resolver.visit(dep, "your project")?;
+            }
         }
-        Ok(())
+        Ok(resolver.packages)
+    }
+
+    /// Downloads the newest matching versions of the packages that are
+    /// updated and writes them to the lockfile. The other packages keep
+    /// their locked versions. `forced` clones the updated packages again.
+    /// Dev and optional dependencies are locked as well
+    pub fn update(&self, update: Update, forced: bool) -> anyhow::Result<()> {
+        let path = util::lockfile();
+        let old = Lockfile::load(&path)?;
+        let resolver = Resolver::new(old.clone(), update, forced, true);
+        let only = match &resolver.update {
+            Update::Package(pkg) => Some(pkg.clone()),
+            _ => None,
+        };
+        let packages = self.resolve_with(resolver, &HashSet::new(), true)?;
+        if let Some(pkg) = only {
+            if !packages.iter().any(|resolved| resolved.name == pkg) {
+                bail!("Package `{}` is not a dependency of your project", pkg);
+            }
+        }
+
+        let mut lock = Lockfile {
+            packages: packages.into_iter().filter_map(|pkg| pkg.locked).collect(),
+        };
+        lock.packages.sort_by(|a, b| a.name.cmp(&b.name));
+        for pkg in &lock.packages {
+            match old.find(&pkg.name) {
+                Some(old_pkg) if old_pkg.commit == pkg.commit => {}
+                Some(old_pkg) => log::status(
+                    "Updating",
+                    format!("{} {} -> {}", pkg.name, old_pkg.revision(), pkg.revision()),
+                ),
+                None => log::status("Locking", format!("{} {}", pkg.name, pkg.revision())),
+            }
+        }
+        for old_pkg in &old.packages {
+            if lock.find(&old_pkg.name).is_none() {
+                log::status("Removing", format!("{} {}", old_pkg.name, old_pkg.revision()));
+            }
+        }
+        lock.save(&path)
     }
 }
 
 impl Dependency {
+    /// Dependency of the positional form `{ "url", "^1.2" }`
     pub fn new(origin: &str, version: &str) -> Self {
-        let origin = match &origin[origin.len() - 4..] {
-            ".git" => origin.to_string(),
-            _ => {
-                let mut origin = origin.to_string();
-                origin.push_str(".git");
-                origin
-            }
+        let origin = if origin.ends_with(".git") {
+            origin.to_string()
+        } else {
+            format!("{}.git", origin)
         };
+        Self::git(&origin, GitRef::Version(version.into()))
+    }
+
+    pub fn git(url: &str, reference: GitRef) -> Self {
         Self {
-            _version: version.into(),
-            origin,
+            source: DepSource::Git {
+                url: url.to_string(),
+                reference,
+            },
+            dev: false,
+            optional: false,
         }
     }
 
-    pub fn name(&self) -> anyhow::Result<String> {
-        let split_path: Vec<&str> = self.origin.split('/').collect();
-        let name = match split_path.last() {
-            Some(name) => name.to_string(),
-            None => bail!("Invalid origin {}", self.origin),
-        };
-        Ok(name[..name.len() - 4].into())
+    /// Creates a dependency on a local project. Relative paths
+    /// are resolved from the depending project's root directory
+    pub fn from_path(root_dir: &Path, path: &str) -> Self {
+        Self {
+            source: DepSource::Path(root_dir.join(path)),
+            dev: false,
+            optional: false,
+        }
     }
 
-    pub fn location(&self) -> anyhow::Result<PathBuf> {
-        let surtur_home = PathBuf::from(env::var(global::SURTUR_HOME)?);
-        let deps_path = surtur_home.join("deps");
-        Ok(deps_path.join(self.name()?))
+    pub fn tarball(url: &str, sha256: &str) -> Self {
+        Self {
+            source: DepSource::Tarball {
+                url: url.to_string(),
+                sha256: sha256.to_lowercase(),
+            },
+            dev: false,
+            optional: false,
+        }
     }
 
-    pub fn config(&self) -> anyhow::Result<Config> {
-        let location = self.location()?;
-        let cfg_path = location.join("project.lua");
-        let file = FileHandler::new(&cfg_path)?;
-        Config::parse(&location, file)
+    /// What the dependency requires, as shown in errors
+    pub fn requirement(&self) -> String {
+        match &self.source {
+            DepSource::Git { url, reference } => format!("{} {}", url, reference.requested()),
+            DepSource::Path(path) => path.display().to_string(),
+            DepSource::Tarball { url, sha256 } => format!("{} with sha256 {}", url, sha256),
+        }
+    }
+
+    pub fn name(&self) -> anyhow::Result<String> {
+        match &self.source {
+            DepSource::Git { url, .. } => {
+                let name = url.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+                let name = name.strip_suffix(".git").unwrap_or(name);
+                if name.is_empty() {
+                    bail!("Invalid origin {}", url);
+                }
+                Ok(name.into())
+            }
+            DepSource::Path(path) => {
+                let path = path
+                    .canonicalize()
+                    .context(format!("Failed to find dependency at {}", path.display()))?;
+                match path.file_name() {
+                    Some(name) => Ok(name.to_string_lossy().to_string()),
+                    None => bail!("Invalid path {}", path.display()),
+                }
+            }
+            DepSource::Tarball { url, .. } => {
+                let file = url.rsplit('/').next().unwrap_or_default();
+                let name = [".tar.gz", ".tgz"]
+                    .iter()
+                    .find_map(|ext| file.strip_suffix(ext))
+                    .unwrap_or(file);
+                if name.is_empty() {
+                    bail!("Invalid archive url {}", url);
+                }
+                Ok(name.into())
+            }
+        }
     }
 }
 
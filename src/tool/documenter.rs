@@ -0,0 +1,649 @@
+//! Handling of generating the API documentation of a
+//! project. The public headers in include/ are parsed
+//! for documentation comments (`///`, `/** */` and `/*! */`)
+//! on functions, structs, enums, unions, typedefs and macros,
+//! which are rendered into a static HTML site and optionally Markdown.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::util::{self, glob, log};
+
+use super::config::Config;
+
+const STYLE: &str = r#"body { font-family: sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; color: #222; }
+a { color: #2a5db0; text-decoration: none; }
+a:hover { text-decoration: underline; }
+pre { background: #f5f5f5; padding: .75rem; overflow-x: auto; border-radius: 4px; }
+.item { margin-bottom: 2rem; }
+.kind { color: #777; font-size: .8em; text-transform: uppercase; }
+nav { margin-bottom: 1.5rem; }
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Function,
+    Struct,
+    Enum,
+    Union,
+    Type,
+    Macro,
+    Variable,
+}
+
+impl ItemKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ItemKind::Function => "function",
+            ItemKind::Struct => "struct",
+            ItemKind::Enum => "enum",
+            ItemKind::Union => "union",
+            ItemKind::Type => "type",
+            ItemKind::Macro => "macro",
+            ItemKind::Variable => "variable",
+        }
+    }
+}
+
+/// A documented declaration of a header
+#[derive(Debug)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub name: String,
+    /// The declaration as written in the header
+    pub code: String,
+    pub doc: String,
+}
+
+#[derive(Debug)]
+pub struct Header {
+    /// Path relative to the include directory, e.g. lib.h
+    pub path: String,
+    /// Comment at the top of the header
+    pub doc: String,
+    pub items: Vec<Item>,
+}
+
+impl Header {
+    /// File name of the header's page without extension
+    fn page(&self) -> String {
+        self.path.replace('/', "-")
+    }
+}
+
+/// Strips the comment markers of a single line of a block comment
+fn block_comment_line(line: &str) -> &str {
+    let line = line.trim();
+    let line = line
+        .strip_prefix("/**")
+        .or_else(|| line.strip_prefix("/*!"))
+        .or_else(|| line.strip_prefix("/*"))
+        .unwrap_or(line);
+    let line = line.strip_suffix("*/").unwrap_or(line);
+    let line = line.trim();
+    line.strip_prefix("* ")
+        .or_else(|| line.strip_prefix('*'))
+        .unwrap_or(line)
+        .trim_end()
+}
+
+/// Removes `//` comments from a line of code, strings are not taken into account
+fn strip_line_comment(line: &str) -> &str {
+    match line.find("//") {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Last identifier in the text
+fn last_ident(text: &str) -> Option<String> {
+    let end = text.rfind(is_ident_char)? + 1;
+    let start = text[..end].rfind(|c| !is_ident_char(c)).map_or(0, |i| i + 1);
+    let ident = &text[start..end];
+    (!ident.starts_with(|c: char| c.is_ascii_digit())).then(|| ident.to_string())
+}
+
+/// Finds out what the statement declares
+fn parse_stmt(stmt: &str) -> Option<(ItemKind, String)> {
+    let stmt = stmt.split_whitespace().collect::<Vec<_>>().join(" ");
+    let words: Vec<&str> = stmt
+        .split(|c: char| !is_ident_char(c))
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let keyword_kind = |word: &str| match word {
+        "struct" => Some(ItemKind::Struct),
+        "enum" => Some(ItemKind::Enum),
+        "union" => Some(ItemKind::Union),
+        _ => None,
+    };
+
+    if words.first() == Some(&"typedef") {
+        // typedef void (*callback)(int);
+        if let Some(start) = stmt.find("(*") {
+            let name = &stmt[start + 2..];
+            let name = &name[..name.find(')')?];
+            return Some((ItemKind::Type, name.trim().to_string()));
+        }
+        let kind = words.get(1).and_then(|word| keyword_kind(word));
+        let after_body = stmt.rfind('}').map_or(stmt.as_str(), |i| &stmt[i..]);
+        let name = last_ident(after_body.trim_end_matches(';'))?;
+        return Some((kind.unwrap_or(ItemKind::Type), name));
+    }
+
+    let first_keyword = words
+        .iter()
+        .position(|word| keyword_kind(word).is_some())
+        .filter(|_| stmt.contains('{'));
+    if let Some(i) = first_keyword {
+        let body_start = stmt.find('{')?;
+        let name = last_ident(&stmt[..body_start])?;
+        if keyword_kind(&name).is_some() {
+            // Anonymous struct or enum without a typedef
+            return None;
+        }
+        return Some((keyword_kind(words[i])?, name));
+    }
+
+    if let Some(paren) = stmt.find('(') {
+        let name = last_ident(&stmt[..paren])?;
+        return Some((ItemKind::Function, name));
+    }
+
+    // Forward declarations like `struct point;` are not documented on their own
+    if words.len() == 2 && keyword_kind(words[0]).is_some() {
+        return None;
+    }
+    let decl = stmt.trim_end_matches(';');
+    let decl = decl.split('=').next().unwrap_or(decl);
+    let decl = decl.split('[').next().unwrap_or(decl);
+    Some((ItemKind::Variable, last_ident(decl)?))
+}
+
+/// Parses the declarations and documentation comments of a header
+pub fn parse_header(path: String, content: &str) -> Header {
+    let mut header = Header {
+        path,
+        doc: String::new(),
+        items: Vec::new(),
+    };
+
+    let mut doc: Vec<String> = Vec::new();
+    // Comment at the start of the file that is not directly attached to an item
+    let mut leading = true;
+    let mut in_comment: Option<bool> = None;
+    let mut stmt = String::new();
+    let mut code = String::new();
+    let mut depth = 0i32;
+    let mut guard: Option<String> = None;
+
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some(is_doc) = in_comment {
+            if is_doc || leading {
+                doc.push(block_comment_line(trimmed).to_string());
+            }
+            if trimmed.contains("*/") {
+                in_comment = None;
+            }
+            continue;
+        }
+
+        if stmt.is_empty() {
+            if trimmed.is_empty() {
+                if leading && !doc.is_empty() {
+                    header.doc = doc.join("\n").trim().to_string();
+                    doc.clear();
+                    leading = false;
+                }
+                continue;
+            }
+            if let Some(text) = trimmed.strip_prefix("///").or_else(|| trimmed.strip_prefix("//!")) {
+                doc.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+                continue;
+            }
+            if trimmed.starts_with("//") {
+                if leading {
+                    doc.push(trimmed.trim_start_matches('/').trim().to_string());
+                }
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix("/*") {
+                let is_doc = trimmed.starts_with("/**") || trimmed.starts_with("/*!");
+                if is_doc || leading {
+                    doc.push(block_comment_line(trimmed).to_string());
+                }
+                if !comment.contains("*/") {
+                    in_comment = Some(is_doc);
+                }
+                continue;
+            }
+            let was_leading = leading;
+            leading = false;
+            if let Some(directive) = trimmed.strip_prefix('#') {
+                let mut directive = directive.trim_start().to_string();
+                let mut raw = line.to_string();
+                // Macros can continue on the next lines
+                while directive.ends_with('\\') {
+                    directive.pop();
+                    let Some(next) = lines.next() else { break };
+                    directive.push_str(next.trim());
+                    raw.push('\n');
+                    raw.push_str(next);
+                }
+                let mut words = directive.split_whitespace();
+                let name = words.next();
+                // The top comment directly followed by an include guard belongs to the header
+                if was_leading && name != Some("define") {
+                    header.doc = doc.join("\n").trim().to_string();
+                }
+                match name {
+                    Some("ifndef") => guard = words.next().map(String::from),
+                    Some("define") => {
+                        let name: String = words
+                            .next()
+                            .unwrap_or_default()
+                            .chars()
+                            .take_while(|c| is_ident_char(*c))
+                            .collect();
+                        let is_guard = guard.as_deref() == Some(name.as_str()) && words.next().is_none();
+                        if !name.is_empty() && !is_guard {
+                            header.items.push(Item {
+                                kind: ItemKind::Macro,
+                                name,
+                                code: raw.trim().to_string(),
+                                doc: doc.join("\n").trim().to_string(),
+                            });
+                        }
+                    }
+                    _ => (),
+                }
+                doc.clear();
+                continue;
+            }
+            // Wrappers for c++ like `extern "C" {` and its closing brace
+            if trimmed.starts_with("extern \"C\"") || trimmed == "}" {
+                doc.clear();
+                continue;
+            }
+        }
+
+        let clean = strip_line_comment(line);
+        depth += clean.matches('{').count() as i32 - clean.matches('}').count() as i32;
+        stmt.push_str(clean);
+        stmt.push('\n');
+        code.push_str(line);
+        code.push('\n');
+
+        let end = stmt.trim_end();
+        let is_definition = end.ends_with('}') && {
+            let before_body = &end[..end.find('{').unwrap_or(0)];
+            !before_body.contains('=') && before_body.trim_end().ends_with(')')
+        };
+        if depth <= 0 && (end.ends_with(';') || is_definition) {
+            if let Some((kind, name)) = parse_stmt(&stmt) {
+                // Only the signature of inline functions is shown
+                let code = match (kind, code.find('{')) {
+                    (ItemKind::Function, Some(i)) => format!("{};", code[..i].trim_end()),
+                    _ => code.trim_end().to_string(),
+                };
+                header.items.push(Item {
+                    kind,
+                    name,
+                    code,
+                    doc: doc.join("\n").trim().to_string(),
+                });
+            }
+            doc.clear();
+            stmt.clear();
+            code.clear();
+            depth = 0;
+        }
+    }
+
+    if leading && !doc.is_empty() {
+        header.doc = doc.join("\n").trim().to_string();
+    }
+    header
+}
+
+/// Where every item is documented, used for cross-links
+type Index = HashMap<String, String>;
+
+fn build_index(headers: &[Header], extension: &str) -> Index {
+    let mut index = HashMap::new();
+    for header in headers {
+        for item in &header.items {
+            index
+                .entry(item.name.clone())
+                .or_insert_with(|| format!("{}.{}#{}", header.page(), extension, item.name));
+        }
+    }
+    index
+}
+
+fn escape_html(c: char, out: &mut String) {
+    match c {
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '&' => out.push_str("&amp;"),
+        '"' => out.push_str("&quot;"),
+        c => out.push(c),
+    }
+}
+
+/// Escapes the text and links every identifier that is documented
+fn link_html(text: &str, index: &Index, skip: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut ident = String::new();
+    let flush = |ident: &mut String, out: &mut String| {
+        match index.get(ident.as_str()) {
+            Some(link) if Some(ident.as_str()) != skip => {
+                let _ = write!(out, "<a href=\"{}\">{}</a>", link, ident);
+            }
+            _ => out.push_str(ident),
+        }
+        ident.clear();
+    };
+    for c in text.chars() {
+        if is_ident_char(c) {
+            ident.push(c);
+        } else {
+            flush(&mut ident, &mut out);
+            escape_html(c, &mut out);
+        }
+    }
+    flush(&mut ident, &mut out);
+    out
+}
+
+/// Turns the comment text into paragraphs
+fn doc_html(doc: &str, index: &Index) -> String {
+    doc.split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| format!("<p>{}</p>\n", link_html(paragraph.trim(), index, None)))
+        .collect()
+}
+
+fn page_html(title: &str, nav: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<nav>{}</nav>\n{}</body>\n</html>\n",
+        title, STYLE, nav, body
+    )
+}
+
+fn write_html(out_dir: &Path, cfg: &Config, headers: &[Header]) -> anyhow::Result<()> {
+    let index = build_index(headers, "html");
+    let title = format!("{} {}", cfg.name, cfg.props.proj_version);
+    let nav = "<a href=\"index.html\">Index</a>";
+
+    let mut body = format!("<h1>{}</h1>\n<h2>Headers</h2>\n<ul>\n", title);
+    for header in headers {
+        let summary = header.doc.lines().next().unwrap_or_default();
+        let _ = writeln!(
+            body,
+            "<li><a href=\"{}.html\">{}</a> {}</li>",
+            header.page(),
+            header.path,
+            link_html(summary, &index, None)
+        );
+    }
+    body.push_str("</ul>\n");
+    fs::write(out_dir.join("index.html"), page_html(&title, nav, &body))
+        .context("Failed to write documentation index")?;
+
+    for header in headers {
+        let mut body = format!("<h1>{}</h1>\n{}", header.path, doc_html(&header.doc, &index));
+        body.push_str("<ul>\n");
+        for item in &header.items {
+            let _ = writeln!(body, "<li><a href=\"#{0}\">{0}</a></li>", item.name);
+        }
+        body.push_str("</ul>\n");
+        for item in &header.items {
+            let _ = write!(
+                body,
+                "<div class=\"item\" id=\"{}\">\n<h3><span class=\"kind\">{}</span> {}</h3>\n<pre><code>{}</code></pre>\n{}</div>\n",
+                item.name,
+                item.kind.name(),
+                item.name,
+                link_html(&item.code, &index, Some(&item.name)),
+                doc_html(&item.doc, &index)
+            );
+        }
+        let path = out_dir.join(format!("{}.html", header.page()));
+        fs::write(&path, page_html(&format!("{} - {}", header.path, title), nav, &body))
+            .context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn write_markdown(out_dir: &Path, cfg: &Config, headers: &[Header]) -> anyhow::Result<()> {
+    let index = build_index(headers, "md");
+    let link = |text: &str| -> String {
+        text.split_inclusive(|c: char| !is_ident_char(c))
+            .map(|part| {
+                let name = part.trim_end_matches(|c: char| !is_ident_char(c));
+                match index.get(name) {
+                    Some(link) => part.replacen(name, &format!("[{}]({})", name, link), 1),
+                    None => part.to_string(),
+                }
+            })
+            .collect()
+    };
+
+    let mut content = format!("# {} {}\n\n## Headers\n\n", cfg.name, cfg.props.proj_version);
+    for header in headers {
+        let summary = header.doc.lines().next().unwrap_or_default();
+        let _ = writeln!(content, "- [{}]({}.md) {}", header.path, header.page(), link(summary));
+    }
+    fs::write(out_dir.join("index.md"), content).context("Failed to write documentation index")?;
+
+    for header in headers {
+        let mut content = format!("# {}\n\n", header.path);
+        if !header.doc.is_empty() {
+            let _ = writeln!(content, "{}\n", link(&header.doc));
+        }
+        for item in &header.items {
+            let _ = write!(
+                content,
+                "<a id=\"{}\"></a>\n### {} `{}`\n\n```c\n{}\n```\n\n",
+                item.name,
+                item.kind.name(),
+                item.name,
+                item.code
+            );
+            if !item.doc.is_empty() {
+                let _ = writeln!(content, "{}\n", link(&item.doc));
+            }
+        }
+        let path = out_dir.join(format!("{}.md", header.page()));
+        fs::write(&path, content).context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Generates the documentation of the project's public headers into build/doc
+pub fn document_proj(proj_dir: &Path, cfg: &Config, markdown: bool) -> anyhow::Result<PathBuf> {
+    let include_dir = proj_dir.join("include");
+    if !include_dir.exists() {
+        bail!("Failed to find the include directory of `{}`, only public headers are documented", cfg.name);
+    }
+
+    let mut files: Vec<PathBuf> = util::get_header_files(&include_dir).into_iter().collect();
+    files.sort();
+
+    let mut headers = Vec::new();
+    for file in files {
+        let content =
+            fs::read_to_string(&file).context(format!("Failed to read header {}", file.display()))?;
+        let path = glob::relative(&file, &include_dir).unwrap_or_else(|| file.display().to_string());
+        log::verbose(format!("Parsing {}", path));
+        headers.push(parse_header(path, &content));
+    }
+
+    let out_dir = util::target_dir().join("doc");
+    log::status("Documenting", format!("{} ({} headers)", cfg.name, headers.len()));
+    if log::is_dry_run() {
+        return Ok(out_dir);
+    }
+    fs::create_dir_all(&out_dir).context("Failed to create build/doc directory")?;
+
+    write_html(&out_dir, cfg, &headers)?;
+    if markdown {
+        let md_dir = out_dir.join("markdown");
+        fs::create_dir_all(&md_dir).context("Failed to create build/doc/markdown directory")?;
+        write_markdown(&md_dir, cfg, &headers)?;
+    }
+    Ok(out_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"/*
+ * Linked lists of integers.
+ */
+#ifndef LIST_H
+#define LIST_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/// Most items a list can hold
+#define LIST_MAX 1024
+
+/** A node of a list
+ *
+ * Nodes are owned by their list. */
+typedef struct node {
+    int value;
+    struct node *next; // the following node
+} node;
+
+// Not documented
+enum color { RED, GREEN };
+
+/// Called for every value
+typedef void (*visit_fn)(int value);
+
+/// Number of nodes after `head`
+size_t list_len(const node *head);
+
+/// Same as list_len, but for at most `max` nodes
+static inline size_t list_len_max(const node *head, size_t max) {
+    size_t len = 0;
+    for (; head && len < max; head = head->next) len++;
+    return len;
+}
+
+/*! Lists created so far */
+extern int list_count;
+
+#ifdef __cplusplus
+}
+#endif
+#endif
+"#;
+
+    fn item<'h>(header: &'h Header, name: &str) -> &'h Item {
+        header.items.iter().find(|item| item.name == name).unwrap()
+    }
+
+    #[test]
+    fn parses_items_in_order() {
+        let header = parse_header("list.h".into(), HEADER);
+        let items: Vec<(ItemKind, &str)> = header.items.iter().map(|item| (item.kind, item.name.as_str())).collect();
+        assert_eq!(
+            items,
+            [
+                (ItemKind::Macro, "LIST_MAX"),
+                (ItemKind::Struct, "node"),
+                (ItemKind::Enum, "color"),
+                (ItemKind::Type, "visit_fn"),
+                (ItemKind::Function, "list_len"),
+                (ItemKind::Function, "list_len_max"),
+                (ItemKind::Variable, "list_count"),
+            ]
+        );
+    }
+
+    #[test]
+    fn top_comment_documents_the_header() {
+        let header = parse_header("list.h".into(), HEADER);
+        assert_eq!(header.doc, "Linked lists of integers.");
+        assert_eq!(item(&header, "LIST_MAX").doc, "Most items a list can hold");
+    }
+
+    #[test]
+    fn only_doc_comments_document_items() {
+        let header = parse_header("list.h".into(), HEADER);
+        assert_eq!(item(&header, "node").doc, "A node of a list\n\nNodes are owned by their list.");
+        assert_eq!(item(&header, "color").doc, "");
+        assert_eq!(item(&header, "list_count").doc, "Lists created so far");
+    }
+
+    #[test]
+    fn code_is_kept_as_written() {
+        let header = parse_header("list.h".into(), HEADER);
+        assert_eq!(item(&header, "LIST_MAX").code, "#define LIST_MAX 1024");
+        assert!(item(&header, "node").code.contains("struct node *next; // the following node"));
+        // Inline functions only show their signature
+        assert_eq!(
+            item(&header, "list_len_max").code,
+            "static inline size_t list_len_max(const node *head, size_t max);"
+        );
+    }
+
+    #[test]
+    fn multi_line_macros() {
+        let header = parse_header("max.h".into(), "/// Larger of both\n#define MAX(a, b) \\\n    ((a) > (b) ? (a) : (b))\n");
+        let max = item(&header, "MAX");
+        assert_eq!(max.kind, ItemKind::Macro);
+        assert_eq!(max.code, "#define MAX(a, b) \\\n    ((a) > (b) ? (a) : (b))");
+        assert_eq!(max.doc, "Larger of both");
+    }
+
+    #[test]
+    fn statements_declare_their_last_name() {
+        assert_eq!(parse_stmt("int *make(int n);"), Some((ItemKind::Function, "make".into())));
+        assert_eq!(parse_stmt("typedef unsigned long id_t;"), Some((ItemKind::Type, "id_t".into())));
+        assert_eq!(parse_stmt("typedef union { int i; float f; } num;"), Some((ItemKind::Union, "num".into())));
+        assert_eq!(parse_stmt("const char *names[4] = { 0 };"), Some((ItemKind::Variable, "names".into())));
+        assert_eq!(parse_stmt("struct point;"), None);
+        assert_eq!(parse_stmt("struct { int x; } origin;"), None);
+    }
+
+    #[test]
+    fn block_comment_markers_are_stripped() {
+        assert_eq!(block_comment_line("/** Text */"), "Text");
+        assert_eq!(block_comment_line(" * more text"), "more text");
+        assert_eq!(block_comment_line("*/"), "");
+        assert_eq!(block_comment_line("/*! Note"), "Note");
+    }
+
+    #[test]
+    fn links_documented_names_and_escapes_html() {
+        let index = Index::from([("node".to_string(), "list.html#node".to_string())]);
+        assert_eq!(
+            link_html("node *a<b && nodes", &index, None),
+            "<a href=\"list.html#node\">node</a> *a&lt;b &amp;&amp; nodes"
+        );
+        assert_eq!(link_html("node", &index, Some("node")), "node");
+    }
+}
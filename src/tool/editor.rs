@@ -0,0 +1,676 @@
+//! Edits the tables of project.lua for `surtur add` and `surtur remove`.
+//! Only the changed entries are touched, the formatting and comments
+//! of the rest of the file stay as they are.
+
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use mlua::{FromLua, Lua, Table};
+
+use crate::util::{log, lua_utils::lua_string, CONFIG_FILE};
+
+use super::{
+    config::Config,
+    deps::{self, Dependency, GitRef},
+    semver::VersionReq,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Open,
+    Close,
+    Separator,
+    Assign,
+    Other,
+}
+
+/// A lua token, comments and whitespace are skipped
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+}
+
+/// An entry of a table and the separator after it
+struct Entry {
+    start: usize,
+    end: usize,
+    sep_end: Option<usize>,
+}
+
+/// A table that is assigned to a global, like `Dependencies = { ... }`
+struct TableSpan {
+    open: usize,
+    close: usize,
+    entries: Vec<Entry>,
+}
+
+/// Source of a project.lua that is edited in place
+struct ConfigEditor {
+    path: PathBuf,
+    src: String,
+}
+
+/// How `surtur add` adds a dependency
+pub struct AddOpts {
+    /// Adds it to the `DevDependencies` table
+    pub dev: bool,
+    /// Only uses it if the feature with its name is enabled
+    pub optional: bool,
+    /// Adds a system library to the `Libraries` table instead
+    pub lib: bool,
+    pub tag: Option<String>,
+    pub rev: Option<String>,
+    pub branch: Option<String>,
+}
+
+fn tokenize(src: &str) -> Vec<Token> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let kind = match bytes[i] {
+            c if c.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                i = match long_bracket(bytes, i + 2) {
+                    Some(level) => close_long_bracket(bytes, i + 2, level),
+                    None => src[i..].find('\n').map_or(bytes.len(), |pos| i + pos),
+                };
+                continue;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+                Kind::Other
+            }
+            b'[' if long_bracket(bytes, i).is_some() => {
+                // Unwrap is safe because of the guard
+                i = close_long_bracket(bytes, i, long_bracket(bytes, i).unwrap());
+                Kind::Other
+            }
+            b'{' | b'(' | b'[' => {
+                i += 1;
+                Kind::Open
+            }
+            b'}' | b')' | b']' => {
+                i += 1;
+                Kind::Close
+            }
+            b',' | b';' => {
+                i += 1;
+                Kind::Separator
+            }
+            b'=' | b'~' | b'<' | b'>' if bytes.get(i + 1) == Some(&b'=') => {
+                i += 2;
+                Kind::Other
+            }
+            b'=' => {
+                i += 1;
+                Kind::Assign
+            }
+            c if c.is_ascii_alphanumeric() || c == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                Kind::Other
+            }
+            _ => {
+                i += src[i..].chars().next().map_or(1, char::len_utf8);
+                Kind::Other
+            }
+        };
+        i = i.min(bytes.len());
+        tokens.push(Token { kind, start, end: i });
+    }
+    tokens
+}
+
+/// Level of the long bracket like `[==[` at the position, if there is one
+fn long_bracket(bytes: &[u8], pos: usize) -> Option<usize> {
+    if bytes.get(pos) != Some(&b'[') {
+        return None;
+    }
+    let level = bytes[pos + 1..].iter().take_while(|&&b| b == b'=').count();
+    (bytes.get(pos + 1 + level) == Some(&b'[')).then_some(level)
+}
+
+/// Position after the bracket that closes the long bracket at the position
+fn close_long_bracket(bytes: &[u8], pos: usize, level: usize) -> usize {
+    let close = format!("]{}]", "=".repeat(level));
+    let from = pos + level + 2;
+    bytes[from..]
+        .windows(close.len())
+        .position(|window| window == close.as_bytes())
+        .map_or(bytes.len(), |found| from + found + close.len())
+}
+
+/// Indentation of the line, if only whitespace comes before the position
+fn indentation(src: &str, pos: usize) -> Option<&str> {
+    let line_start = src[..pos].rfind('\n').map_or(0, |found| found + 1);
+    let indent = &src[line_start..pos];
+    indent.trim().is_empty().then_some(indent)
+}
+
+fn eval<T: FromLua>(lua: &Lua, text: &str) -> anyhow::Result<T> {
+    Ok(lua.load(format!("return {}", text)).eval()?)
+}
+
+impl ConfigEditor {
+    fn load(proj_dir: &Path) -> anyhow::Result<Self> {
+        let path = proj_dir.join(CONFIG_FILE);
+        let src = fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        Ok(Self { path, src })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        fs::write(&self.path, &self.src).context(format!("Failed to write {}", self.path.display()))
+    }
+
+    fn text(&self, entry: &Entry) -> &str {
+        &self.src[entry.start..entry.end]
+    }
+
+    fn table(&self, name: &str) -> Option<TableSpan> {
+        let tokens = tokenize(&self.src);
+        let text = |token: &Token| &self.src[token.start..token.end];
+        let mut depth = 0usize;
+        let mut open = None;
+        for (idx, token) in tokens.iter().enumerate() {
+            match token.kind {
+                Kind::Open => depth += 1,
+                Kind::Close => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if depth == 0
+                && text(token) == name
+                && (idx == 0 || !matches!(text(&tokens[idx - 1]), "." | ":"))
+                && tokens.get(idx + 1).is_some_and(|token| token.kind == Kind::Assign)
+                && tokens.get(idx + 2).is_some_and(|token| text(token) == "{")
+            {
+                open = Some(idx + 2);
+                break;
+            }
+        }
+
+        let open = open?;
+        let mut depth = 0usize;
+        let mut entries = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+        for token in &tokens[open + 1..] {
+            match token.kind {
+                Kind::Close if depth == 0 => {
+                    if let Some((start, end)) = current {
+                        entries.push(Entry { start, end, sep_end: None });
+                    }
+                    return Some(TableSpan {
+                        open: tokens[open].start,
+                        close: token.start,
+                        entries,
+                    });
+                }
+                Kind::Separator if depth == 0 => {
+                    if let Some((start, end)) = current.take() {
+                        entries.push(Entry {
+                            start,
+                            end,
+                            sep_end: Some(token.end),
+                        });
+                    }
+                }
+                kind => {
+                    match kind {
+                        Kind::Open => depth += 1,
+                        Kind::Close => depth -= 1,
+                        _ => {}
+                    }
+                    let start = current.map_or(token.start, |(start, _)| start);
+                    current = Some((start, token.end));
+                }
+            }
+        }
+        None
+    }
+
+    fn entries(&self, table: &str) -> Vec<Entry> {
+        self.table(table).map(|table| table.entries).unwrap_or_default()
+    }
+
+    /// Adds the entry to the end of the table, a missing table is added to the end of the file
+    fn insert(&mut self, table: &str, text: &str) {
+        let Some(span) = self.table(table) else {
+            if !self.src.is_empty() && !self.src.ends_with('\n') {
+                self.src.push('\n');
+            }
+            self.src.push_str(&format!("\n{} = {{\n    {},\n}}\n", table, text));
+            return;
+        };
+
+        let last = span.entries.last();
+        let close_indent = indentation(&self.src, span.close);
+        // Tables on a single line get the entry on the same line
+        if close_indent.is_none() || !self.src[span.open..span.close].contains('\n') {
+            match last {
+                Some(Entry { end, sep_end: None, .. }) => self.src.insert_str(*end, &format!(", {}", text)),
+                Some(Entry { sep_end: Some(sep_end), .. }) => self.src.insert_str(*sep_end, &format!(" {}", text)),
+                None => self.src.replace_range(span.open + 1..span.close, &format!(" {} ", text)),
+            }
+            return;
+        }
+
+        let indent = span
+            .entries
+            .first()
+            .and_then(|first| indentation(&self.src, first.start))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}    ", close_indent.unwrap_or_default()));
+        let line_start = span.close - close_indent.map_or(0, str::len);
+        let last_end = match last {
+            Some(Entry { end, sep_end: None, .. }) => Some(*end),
+            _ => None,
+        };
+        self.src.insert_str(line_start, &format!("{}{},\n", indent, text));
+        if let Some(end) = last_end {
+            self.src.insert(end, ',');
+        }
+    }
+
+    fn replace(&mut self, entry: &Entry, text: &str) {
+        self.src.replace_range(entry.start..entry.end, text);
+    }
+
+    /// Removes the entry, with its line if nothing but a comment shares it
+    fn remove(&mut self, entry: &Entry) {
+        let end = entry.sep_end.unwrap_or(entry.end);
+        let line_end = self.src[end..].find('\n').map_or(self.src.len(), |found| end + found + 1);
+        let rest = self.src[end..line_end].trim();
+        match indentation(&self.src, entry.start) {
+            Some(indent) if rest.is_empty() || rest.starts_with("--") => {
+                self.src.replace_range(entry.start - indent.len()..line_end, "");
+            }
+            _ => {
+                let spaces = self.src[end..].len() - self.src[end..].trim_start_matches(' ').len();
+                self.src.replace_range(entry.start..end + spaces, "");
+            }
+        }
+    }
+}
+
+/// Name of the dependency of an entry, `None` if the entry is no valid dependency.
+/// Path dependencies whose directory is gone are named after the path as written
+fn dep_name(proj_dir: &Path, text: &str) -> Option<String> {
+    let lua = Lua::new();
+    let table: Table = eval(&lua, text).ok()?;
+    let path = table.get::<Option<String>>("path").ok().flatten();
+    let dep = Config::parse_dep(proj_dir, table).ok()?;
+    dep.name().ok().or_else(|| path_name(&proj_dir.join(path?)))
+}
+
+/// Last part of the path after resolving `.` and `..`, without looking at the file system
+fn path_name(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop();
+            }
+            comp => parts.push(comp),
+        }
+    }
+    match parts.last()? {
+        Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+        _ => None,
+    }
+}
+
+/// Path relative to the project directory with `/` as separator
+fn relative_path(proj_dir: &Path, path: &Path) -> anyhow::Result<String> {
+    let proj_dir = proj_dir.canonicalize()?;
+    let path = path
+        .canonicalize()
+        .context(format!("Failed to find {}", path.display()))?;
+    let common = proj_dir
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts = vec![String::from(".."); proj_dir.components().count() - common];
+    parts.extend(
+        path.components()
+            .skip(common)
+            .map(|comp| comp.as_os_str().to_string_lossy().to_string()),
+    );
+    Ok(if parts.is_empty() { ".".into() } else { parts.join("/") })
+}
+
+/// Expands `user/repo` to a GitHub repository and plain names to repositories of the Surtur team
+fn git_url(source: &str) -> anyhow::Result<String> {
+    let path = Path::new(source);
+    Ok(if source.contains("://") || source.starts_with("git@") {
+        source.into()
+    } else if path.exists() {
+        path.canonicalize()?.to_string_lossy().to_string()
+    } else if source.contains('/') {
+        format!("https://github.com/{}", source)
+    } else {
+        format!("https://github.com/Surtur-Team/{}", source)
+    })
+}
+
+/// Splits `source@version`, the `@` of urls like `git@github.com:user/repo` is kept
+fn split_version(spec: &str) -> (&str, Option<&str>) {
+    match spec.rsplit_once('@') {
+        Some((source, version)) if !source.is_empty() && !version.contains(['/', ':']) => {
+            (source, Some(version))
+        }
+        _ => (spec, None),
+    }
+}
+
+/// Adds a dependency or library to project.lua, an entry of the dependency that is
+/// already there is replaced. Returns the name of the dependency that has to be locked
+pub fn add_dep(proj_dir: &Path, spec: &str, opts: &AddOpts) -> anyhow::Result<Option<String>> {
+    let mut editor = ConfigEditor::load(proj_dir)?;
+
+    if opts.lib {
+        let exists = editor
+            .entries("Libraries")
+            .iter()
+            .any(|entry| eval::<String>(&Lua::new(), editor.text(entry)).is_ok_and(|lib| lib == spec));
+        if exists {
+            log::status("Skipping", format!("library {}, it is already linked", spec));
+            return Ok(None);
+        }
+        editor.insert("Libraries", &lua_string(spec));
+        log::status("Adding", format!("library {}", spec));
+        if !log::is_dry_run() {
+            editor.save()?;
+        }
+        return Ok(None);
+    }
+
+    let (source, version) = split_version(spec);
+    let has_ref = opts.tag.is_some() || opts.rev.is_some() || opts.branch.is_some();
+    let (name, mut text, requested) = if version.is_none() && Path::new(source).join(CONFIG_FILE).is_file() {
+        if has_ref {
+            bail!("Path dependencies can't have a `--tag`, `--rev` or `--branch`");
+        }
+        let path = relative_path(proj_dir, Path::new(source))?;
+        let name = Dependency::from_path(proj_dir, &path).name()?;
+        (name, format!("{{ path = {}", lua_string(&path)), format!("at {}", path))
+    } else if [".tar.gz", ".tgz"].iter().any(|ext| source.ends_with(ext)) {
+        bail!("Archives need a `sha256` checksum, add {} to {} by hand", source, CONFIG_FILE);
+    } else {
+        let url = git_url(source)?;
+        let name = Dependency::git(&url, GitRef::DefaultBranch).name()?;
+        let reference = match (version, &opts.tag, &opts.rev, &opts.branch) {
+            (Some(req), None, None, None) => {
+                VersionReq::parse(req).context(format!("Invalid version of dependency {}", name))?;
+                GitRef::Version(req.into())
+            }
+            (None, Some(tag), None, None) => GitRef::Tag(tag.clone()),
+            (None, None, Some(rev), None) => GitRef::Rev(rev.clone()),
+            (None, None, None, Some(branch)) => GitRef::Branch(branch.clone()),
+            // Without a version the newest release is required, like cargo does it
+            (None, None, None, None) => match deps::newest_version(&url, &name)? {
+                Some(version) => GitRef::Version(format!("^{}", version)),
+                None => GitRef::DefaultBranch,
+            },
+            _ => bail!("Dependency {} can only have one of a version, a tag, a rev and a branch", name),
+        };
+        let mut text = format!("{{ git = {}", lua_string(&url));
+        match &reference {
+            GitRef::Version(req) => text.push_str(&format!(", version = {}", lua_string(req))),
+            GitRef::Tag(tag) => text.push_str(&format!(", tag = {}", lua_string(tag))),
+            GitRef::Rev(rev) => text.push_str(&format!(", rev = {}", lua_string(rev))),
+            GitRef::Branch(branch) => text.push_str(&format!(", branch = {}", lua_string(branch))),
+            GitRef::DefaultBranch => {}
+        }
+        (name, text, reference.requested())
+    };
+    if opts.optional {
+        text.push_str(", optional = true");
+    }
+    text.push_str(" }");
+
+    let table = if opts.dev { "DevDependencies" } else { "Dependencies" };
+    let existing = editor
+        .entries(table)
+        .into_iter()
+        .find(|entry| dep_name(proj_dir, editor.text(entry)).is_some_and(|found| found == name));
+    match existing {
+        Some(entry) => {
+            log::status("Replacing", format!("{} with {}", name, requested));
+            editor.replace(&entry, &text);
+        }
+        None => {
+            log::status("Adding", format!("{} {}", name, requested));
+            editor.insert(table, &text);
+        }
+    }
+    if log::is_dry_run() {
+        return Ok(None);
+    }
+    editor.save()?;
+    Ok(Some(name))
+}
+
+/// Removes every entry of the dependency or library from project.lua.
+/// Returns whether the lockfile has to be updated
+pub fn remove_dep(proj_dir: &Path, name: &str, lib: bool) -> anyhow::Result<bool> {
+    let mut editor = ConfigEditor::load(proj_dir)?;
+    let tables: &[&str] = if lib {
+        &["Libraries"]
+    } else {
+        &["Dependencies", "DevDependencies"]
+    };
+
+    let mut removed = false;
+    for table in tables {
+        loop {
+            let entry = editor.entries(table).into_iter().find(|entry| {
+                let text = editor.text(entry);
+                if lib {
+                    eval::<String>(&Lua::new(), text).is_ok_and(|found| found == name)
+                } else {
+                    dep_name(proj_dir, text).is_some_and(|found| found == name)
+                }
+            });
+            let Some(entry) = entry else {
+                break;
+            };
+            editor.remove(&entry);
+            removed = true;
+        }
+    }
+
+    if !removed {
+        let kind = if lib { "library" } else { "dependency" };
+        bail!("`{}` is not a {} in {}", name, kind, CONFIG_FILE);
+    }
+    log::status("Removing", name);
+    if log::is_dry_run() {
+        return Ok(false);
+    }
+    editor.save()?;
+    Ok(!lib)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROJECT: &str = r#"Name = "app"
+
+-- external dependencies
+Dependencies = {
+    -- pinned until the next release
+    { git = "https://github.com/user/args", version = "^1.0" }
+}
+
+Libraries = {}
+"#;
+
+    fn editor(src: &str) -> ConfigEditor {
+        ConfigEditor {
+            path: PathBuf::new(),
+            src: src.into(),
+        }
+    }
+
+    /// Project directory with the config, removed again by `cleanup`
+    fn project(name: &str, src: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("surtur-editor-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(CONFIG_FILE), src).unwrap();
+        dir
+    }
+
+    fn cleanup(dir: &Path) {
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn opts() -> AddOpts {
+        AddOpts {
+            dev: false,
+            optional: false,
+            lib: false,
+            tag: None,
+            rev: None,
+            branch: None,
+        }
+    }
+
+    #[test]
+    fn insert_adds_a_line_to_multi_line_tables() {
+        let mut editor = editor(PROJECT);
+        editor.insert("Dependencies", r#"{ path = "../util" }"#);
+        assert!(editor.src.contains(concat!(
+            "    -- pinned until the next release\n",
+            "    { git = \"https://github.com/user/args\", version = \"^1.0\" },\n",
+            "    { path = \"../util\" },\n",
+            "}\n",
+        )));
+    }
+
+    #[test]
+    fn insert_keeps_single_line_tables_on_one_line() {
+        let mut editor = editor(PROJECT);
+        editor.insert("Libraries", r#""m""#);
+        assert!(editor.src.contains(r#"Libraries = { "m" }"#));
+        editor.insert("Libraries", r#""pthread""#);
+        assert!(editor.src.contains(r#"Libraries = { "m", "pthread" }"#));
+    }
+
+    #[test]
+    fn insert_appends_missing_tables() {
+        let mut editor = editor("Name = \"app\"");
+        editor.insert("DevDependencies", r#"{ path = "../tests" }"#);
+        assert_eq!(editor.src, "Name = \"app\"\n\nDevDependencies = {\n    { path = \"../tests\" },\n}\n");
+    }
+
+    #[test]
+    fn tables_in_strings_and_comments_are_ignored() {
+        let editor = editor("-- Libraries = { \"x\" }\nName = \"Libraries = {}\"\nLibraries = { \"m\"; \"dl\" }\n");
+        let texts: Vec<&str> = editor.entries("Libraries").iter().map(|entry| editor.text(entry)).collect();
+        assert_eq!(texts, [r#""m""#, r#""dl""#]);
+    }
+
+    #[test]
+    fn remove_keeps_comments_of_other_lines() {
+        let mut editor = editor(PROJECT);
+        let entry = editor.entries("Dependencies").remove(0);
+        editor.remove(&entry);
+        assert!(editor.src.contains("Dependencies = {\n    -- pinned until the next release\n}\n"));
+    }
+
+    #[test]
+    fn remove_of_inline_entries_leaves_the_rest_of_the_line() {
+        let mut editor = editor("Libraries = { \"m\", \"dl\", \"pthread\" }\n");
+        let entry = editor.entries("Libraries").remove(1);
+        editor.remove(&entry);
+        assert_eq!(editor.src, "Libraries = { \"m\", \"pthread\" }\n");
+    }
+
+    #[test]
+    fn splits_versions_of_specs() {
+        assert_eq!(split_version("args@^1.2"), ("args", Some("^1.2")));
+        assert_eq!(split_version("user/args"), ("user/args", None));
+        assert_eq!(split_version("git@github.com:user/args"), ("git@github.com:user/args", None));
+        assert_eq!(split_version("git@github.com:user/args@1.0"), ("git@github.com:user/args", Some("1.0")));
+    }
+
+    #[test]
+    fn add_replaces_the_entry_of_the_same_dependency() {
+        let dir = project("replace", PROJECT);
+        let name = add_dep(&dir, "user/args@=1.4", &opts()).unwrap();
+        let src = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        cleanup(&dir);
+
+        assert_eq!(name.as_deref(), Some("args"));
+        assert!(src.contains("    { git = \"https://github.com/user/args\", version = \"=1.4\" }\n}"));
+        assert_eq!(src.matches("user/args").count(), 1);
+    }
+
+    #[test]
+    fn add_and_remove_dev_dependency() {
+        let dir = project("dev", PROJECT);
+        let mut dev = opts();
+        dev.dev = true;
+        dev.optional = true;
+        add_dep(&dir, "user/bench@^0.3", &dev).unwrap();
+        let added = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        let needs_relock = remove_dep(&dir, "bench", false).unwrap();
+        let removed = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        let missing = remove_dep(&dir, "bench", false);
+        cleanup(&dir);
+
+        assert!(added.ends_with(
+            "DevDependencies = {\n    { git = \"https://github.com/user/bench\", version = \"^0.3\", optional = true },\n}\n"
+        ));
+        assert!(needs_relock);
+        assert_eq!(removed, format!("{}\nDevDependencies = {{\n}}\n", PROJECT));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn remove_path_dependency_whose_directory_is_gone() {
+        let dir = project("gone", "Dependencies = {\n    { path = \"./libs/../vendor/util/\" },\n}\n");
+        let needs_relock = remove_dep(&dir, "util", false).unwrap();
+        let removed = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        cleanup(&dir);
+
+        assert!(needs_relock);
+        assert_eq!(removed, "Dependencies = {\n}\n");
+    }
+
+    #[test]
+    fn add_and_remove_library() {
+        let dir = project("lib", PROJECT);
+        let mut lib = opts();
+        lib.lib = true;
+        assert_eq!(add_dep(&dir, "m", &lib).unwrap(), None);
+        add_dep(&dir, "m", &lib).unwrap();
+        let added = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        let needs_relock = remove_dep(&dir, "m", true).unwrap();
+        let removed = fs::read_to_string(dir.join(CONFIG_FILE)).unwrap();
+        cleanup(&dir);
+
+        assert!(added.contains(r#"Libraries = { "m" }"#));
+        assert!(!needs_relock);
+        assert!(removed.contains("Libraries = { }"));
+    }
+}
@@ -2,29 +2,74 @@
 //! the Compiler for easily running and building
 //! everything
 
-use std::{env, fs, path::PathBuf, process::Command};
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::Command,
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
 use crate::{
     cli::Cli,
-    util::{self, MISSING_CFG},
+    global,
+    util::{self, lock::DirLock, log, MISSING_CFG},
 };
 
 use super::{
     compiler::{CompileCtx, Compiler},
-    config::ProjType,
+    config::{Config, ProjType},
+    graph::{self, Action, BuildGraph, NodeId, NodeKind},
+    workspace::Workspace,
 };
 
-pub fn run_c(cli: Cli, enable_dbg: bool, args: Option<Vec<&String>>) -> anyhow::Result<()> {
-    let cur_dir = cli.cur_dir.clone();
-    let root_name = util::root_dir_name(&cur_dir);
-    let executable_path = format!(
-        "./build/{}",
-        root_name.context("Failed to get root name of project")?
-    );
+pub const RELEASE_DIR: &str = "release";
+
+/// The part of the project that should be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target<'t> {
+    /// The main executable or library of the project
+    Main,
+    /// A single binary from src/bin or the `Binaries` table
+    Bin(&'t str),
+    /// All binaries of the project
+    Bins,
+    /// A single example from the examples directory
+    Example(&'t str),
+    /// All examples of the project
+    Examples,
+    /// A single benchmark from the benches directory
+    Bench(&'t str),
+    /// All benchmarks of the project
+    Benches,
+}
+
+pub fn run_c(
+    cli: Cli,
+    target: Target,
+    enable_dbg: bool,
+    args: Option<Vec<&String>>,
+) -> anyhow::Result<()> {
+    let cfg = cli.cfg.context(MISSING_CFG)?;
+    run_proj(&cli.cur_dir, cfg, target, enable_dbg, args)
+}
+
+pub fn run_proj(
+    proj_dir: &PathBuf,
+    mut cfg: Config,
+    target: Target,
+    enable_dbg: bool,
+    args: Option<Vec<&String>>,
+) -> anyhow::Result<()> {
+    let root_name = util::root_dir_name(proj_dir);
+    let out_dir = profile_dir(false);
+    let executable_path = match target {
+        Target::Bin(name) => out_dir.join("bin").join(name),
+        Target::Example(name) => out_dir.join("examples").join(name),
+        _ => out_dir.join(root_name.context("Failed to get root name of project")?),
+    };
 
-    self::build_c(cli, enable_dbg, true, false)?;
+    self::build_proj(proj_dir, &mut cfg, target, enable_dbg, true, false)?;
 
     // Create a Command to run the executable
     let mut cmd = Command::new(executable_path);
@@ -32,69 +77,323 @@ pub fn run_c(cli: Cli, enable_dbg: bool, args: Option<Vec<&String>>) -> anyhow::
         cmd.args(args);
     }
 
-    util::run_c_program(&mut cmd, &cur_dir)
+    util::run_c_program(&mut cmd, proj_dir)
 }
 
 pub fn build_c(
     cli: Cli,
+    target: Target,
     enable_dbg: bool,
     direct_execution: bool,
     is_release: bool,
 ) -> anyhow::Result<()> {
     let mut cfg = cli.cfg.context(MISSING_CFG)?;
-    if let Some(sm) = &cfg.scripts {
-        sm.pre_exec().context("Failed to run build scripts")?;
+    build_proj(&cli.cur_dir, &mut cfg, target, enable_dbg, direct_execution, is_release)
+}
+
+/// Builds every member of the workspace into the workspace's
+/// build directory
+pub fn build_workspace(
+    ws: Workspace,
+    target: Target,
+    enable_dbg: bool,
+    is_release: bool,
+) -> anyhow::Result<()> {
+    for mut member in ws.members {
+        build_proj(&member.dir, &mut member.cfg, target, enable_dbg, false, is_release)
+            .context(format!("Failed to build workspace member `{}`", member.cfg.name))?;
     }
+    Ok(())
+}
+
+/// Keeps other surtur processes from using the build
+/// directory until the returned lock is dropped
+pub fn lock_build_dir() -> anyhow::Result<DirLock> {
+    DirLock::exclusive(&util::target_dir(), "the build directory")
+}
 
-    if direct_execution {
+/// Directory the outputs of a build profile go to.
+/// Release builds are kept apart from debug builds
+pub fn profile_dir(is_release: bool) -> PathBuf {
+    let build_dir = util::target_dir();
+    if is_release {
+        build_dir.join(RELEASE_DIR)
+    } else {
+        build_dir
+    }
+}
+
+pub fn build_proj(
+    proj_dir: &PathBuf,
+    cfg: &mut Config,
+    target: Target,
+    enable_dbg: bool,
+    direct_execution: bool,
+    is_release: bool,
+) -> anyhow::Result<()> {
+    let _lock = lock_build_dir()?;
+
+    if direct_execution && target == Target::Main {
         cfg.props.proj_type = ProjType::Bin;
     }
+    cfg.dev = matches!(
+        target,
+        Target::Example(_) | Target::Examples | Target::Bench(_) | Target::Benches
+    );
 
-    let compiler = Compiler::new(&cli.cur_dir, &cfg)?;
+    let compiler = Compiler::new(proj_dir, cfg)?;
 
-    let mut root_name = util::root_dir_name(&cli.cur_dir)
+    let mut root_name = util::root_dir_name(proj_dir)
         .context("Failed to get root name of project")?
         .to_string();
 
-    let out_path = PathBuf::from("build");
+    let out_path = profile_dir(is_release);
 
     if cfg.props.proj_type == ProjType::Lib {
         root_name.push_str(".a");
     }
 
-    if fs::metadata("./build").is_err() {
-        fs::create_dir("./build").context("Failed to create build directory")?
+    if !out_path.exists() {
+        fs::create_dir_all(&out_path).context("Failed to create build directory")?
+    }
+
+    let mut graph = BuildGraph::new();
+    let mut after = Vec::new();
+
+    if let Some(sm) = &cfg.scripts {
+        let task = Box::new(|| sm.pre_exec().context("Failed to run build scripts"));
+        after.push(graph.add(NodeKind::Script, "pre-build scripts", Action::Task(task), &[]));
+    }
+
+    after.extend(
+        compiler
+            .build_deps(&mut graph, &after)
+            .context("Failed to build dependencies")?,
+    );
+
+    let mut outputs = Vec::new();
+    match target {
+        Target::Main => {
+            let sources = cfg.active_sources();
+            let ctx = CompileCtx {
+                out_dir: &out_path,
+                root_dir: proj_dir,
+                out_name: &root_name,
+                sources: &sources,
+                objects: &cfg.objects,
+            };
+
+            outputs.extend(compiler.build(&mut graph, ctx, enable_dbg, is_release, false, &after)?);
+        }
+        Target::Bin(name) => {
+            outputs.push(build_bin(&mut graph, proj_dir, cfg, &compiler, name, enable_dbg, is_release, &after)?)
+        }
+        Target::Bins => {
+            for name in cfg.binaries.keys() {
+                outputs.push(build_bin(&mut graph, proj_dir, cfg, &compiler, name, enable_dbg, is_release, &after)?);
+            }
+        }
+        Target::Example(name) => {
+            outputs.push(build_example(&mut graph, proj_dir, cfg, &compiler, name, enable_dbg, is_release, &after)?)
+        }
+        Target::Examples => {
+            for name in cfg.examples.keys() {
+                outputs.push(build_example(&mut graph, proj_dir, cfg, &compiler, name, enable_dbg, is_release, &after)?);
+            }
+        }
+        Target::Bench(name) => outputs.push(build_bench(&mut graph, proj_dir, cfg, &compiler, name, &after)?),
+        Target::Benches => {
+            for name in cfg.benches.keys() {
+                outputs.push(build_bench(&mut graph, proj_dir, cfg, &compiler, name, &after)?);
+            }
+        }
+    }
+
+    if let Some(sm) = &cfg.scripts {
+        // Without outputs, post scripts still have to wait for everything else
+        if outputs.is_empty() {
+            outputs = after;
+        }
+        let task = Box::new(|| sm.post_exec().context("Failed to run post build process scripts"));
+        graph.add(NodeKind::Script, "post-build scripts", Action::Task(task), &outputs);
+    }
+
+    let _deps_lock = global::lock_deps(true)?;
+    graph.run(&cfg.name)?;
+
+    if !graph::is_plan_only() {
+        log::status("Finished", format!("building {}", cfg.name));
     }
 
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_bin(
+    graph: &mut BuildGraph,
+    proj_dir: &PathBuf,
+    cfg: &Config,
+    compiler: &Compiler,
+    name: &str,
+    enable_dbg: bool,
+    is_release: bool,
+    after: &[NodeId],
+) -> anyhow::Result<NodeId> {
+    let bin_dir = profile_dir(is_release).join("bin");
+
+    if !bin_dir.exists() {
+        fs::create_dir(&bin_dir).context("Failed to create build/bin directory")?
+    }
+
+    let sources = cfg.active_sources();
+    let ctx = CompileCtx {
+        out_dir: &bin_dir,
+        root_dir: proj_dir,
+        out_name: name,
+        sources: &sources,
+        objects: &cfg.objects,
+    };
+
     compiler
-        .build_deps()
-        .context("Failed to build dependencies")?;
-        
+        .build_bin(graph, ctx, name, enable_dbg, is_release, after)
+        .context(format!("Failed to build binary `{}`", name))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_example(
+    graph: &mut BuildGraph,
+    proj_dir: &PathBuf,
+    cfg: &Config,
+    compiler: &Compiler,
+    name: &str,
+    enable_dbg: bool,
+    is_release: bool,
+    after: &[NodeId],
+) -> anyhow::Result<NodeId> {
+    let examples_dir = profile_dir(is_release).join("examples");
+
+    if !examples_dir.exists() {
+        fs::create_dir(&examples_dir).context("Failed to create build/examples directory")?
+    }
+
+    let sources = cfg.active_sources();
     let ctx = CompileCtx {
-        out_dir: &out_path,
-        root_dir: &cli.cur_dir,
-        out_name: &root_name,
-        excluded: &cfg.excluded,
+        out_dir: &examples_dir,
+        root_dir: proj_dir,
+        out_name: name,
+        sources: &sources,
+        objects: &cfg.objects,
     };
 
-    compiler.build(ctx, enable_dbg, is_release, false)?;
+    compiler
+        .build_example(graph, ctx, name, enable_dbg, is_release, after)
+        .context(format!("Failed to build example `{}`", name))
+}
+
+fn build_bench(
+    graph: &mut BuildGraph,
+    proj_dir: &PathBuf,
+    cfg: &Config,
+    compiler: &Compiler,
+    name: &str,
+    after: &[NodeId],
+) -> anyhow::Result<NodeId> {
+    let bench_dir = util::target_dir().join("bench").join("bin");
 
-    if let Some(sm) = &cfg.scripts {
-        sm.post_exec().context("Failed to run post build process scripts")?;
+    if !bench_dir.exists() {
+        fs::create_dir_all(&bench_dir).context("Failed to create build/bench/bin directory")?
+    }
+
+    let sources = cfg.active_sources();
+    let ctx = CompileCtx {
+        out_dir: &bench_dir,
+        root_dir: proj_dir,
+        out_name: name,
+        sources: &sources,
+        objects: &cfg.objects,
+    };
+
+    compiler
+        .build_bench(graph, ctx, name, after)
+        .context(format!("Failed to build benchmark `{}`", name))
+}
+
+/// Checks the project for errors without building it
+pub fn check_proj(proj_dir: &PathBuf, mut cfg: Config, tests: bool) -> anyhow::Result<()> {
+    // Examples and benches are checked too and build with dev dependencies
+    cfg.dev = tests || !cfg.examples.is_empty() || !cfg.benches.is_empty();
+    let compiler = Compiler::new(proj_dir, &cfg)?;
+    let root_name = compiler.root_name;
+    let sources = cfg.active_sources();
+    let ctx = CompileCtx {
+        out_dir: proj_dir,
+        root_dir: proj_dir,
+        out_name: root_name,
+        sources: &sources,
+        objects: &cfg.objects,
+    };
+
+    let failed = compiler.check(ctx, tests)?;
+    if failed != 0 {
+        bail!("Could not check `{}` due to errors in {} file(s)", root_name, failed);
     }
+    log::status("Finished", format!("checking {}", root_name));
+    Ok(())
+}
 
+/// Checks every workspace member and reports all members with errors
+pub fn check_workspace(ws: Workspace, tests: bool) -> anyhow::Result<()> {
+    let mut failed = Vec::new();
+    for member in ws.members {
+        let name = member.cfg.name.clone();
+        if let Err(err) = check_proj(&member.dir, member.cfg, tests) {
+            eprintln!("{:#}", err);
+            failed.push(name);
+        }
+    }
+    if !failed.is_empty() {
+        bail!("Checking failed for workspace members: {}", failed.join(", "));
+    }
     Ok(())
 }
 
-pub fn run_test(cli: Cli, tests: &str) -> anyhow::Result<()> {
-    let mut cfg = cli.cfg.context(MISSING_CFG)?;
+/// Runs the tests of every workspace member and stops
+/// at the first member whose tests fail
+pub fn run_workspace_tests(ws: Workspace, tests: &str) -> anyhow::Result<()> {
+    for member in ws.members {
+        let name = member.cfg.name.clone();
+        let code = test_proj(&member.dir, member.cfg, tests)?;
+        if code != 0 {
+            bail!("Tests of workspace member `{}` failed with exit code {}", name, code);
+        }
+    }
+    Ok(())
+}
+
+/// Builds and runs the tests of a project and
+/// returns the exit code of the test program
+pub fn test_proj(proj_dir: &PathBuf, cfg: Config, tests: &str) -> anyhow::Result<i32> {
+    let test_exe = build_tests(proj_dir, cfg)?;
+
+    env::set_var("SURTUR_TESTS", tests);
+    log::debug(format!("SURTUR_TESTS={}", tests));
+
+    let mut program = Command::new(test_exe);
+
+    util::wait_c_program(&mut program, proj_dir)
+}
+
+/// Builds the test executable of a project and returns its path
+pub fn build_tests(proj_dir: &PathBuf, mut cfg: Config) -> anyhow::Result<PathBuf> {
+    let _lock = lock_build_dir()?;
     cfg.props.proj_type = ProjType::Bin;
-    let compiler = Compiler::new(&cli.cur_dir, &cfg)?;
+    cfg.dev = true;
+    let compiler = Compiler::new(proj_dir, &cfg)?;
 
-    let build_dir = PathBuf::from("build");
+    let build_dir = util::target_dir();
 
     if !build_dir.exists() {
-        fs::create_dir(&build_dir).context("Failed to create build directory")?
+        fs::create_dir_all(&build_dir).context("Failed to create build directory")?
     }
 
     let tests_dir = build_dir.join("tests");
@@ -103,20 +402,22 @@ pub fn run_test(cli: Cli, tests: &str) -> anyhow::Result<()> {
         fs::create_dir(&tests_dir).context("Failed to create build/tests directory")?
     }
 
-    compiler.build_deps()?;
+    let mut graph = BuildGraph::new();
+    let deps = compiler.build_deps(&mut graph, &[])?;
 
+    let sources = cfg.active_sources();
     let ctx = CompileCtx {
-        excluded: &cfg.excluded,
+        sources: &sources,
+        objects: &cfg.objects,
         out_dir: &tests_dir,
-        root_dir: &cli.cur_dir,
+        root_dir: proj_dir,
         out_name: &cfg.name,
     };
 
-    compiler.build(ctx, true, false, true)?;
-
-    env::set_var("SURTUR_TESTS", tests);
+    compiler.build(&mut graph, ctx, true, false, true, &deps)?;
 
-    let mut program = Command::new(tests_dir.join(cfg.name));
+    let _deps_lock = global::lock_deps(true)?;
+    graph.run(&cfg.name)?;
 
-    util::run_c_program(&mut program, &cli.cur_dir)
+    Ok(tests_dir.join(cfg.name))
 }
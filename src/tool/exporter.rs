@@ -0,0 +1,525 @@
+//! Handling of exporting the build of a project as a
+//! Makefile, CMakeLists.txt or build.ninja, so it can be
+//! built without surtur. The exported commands are the
+//! same the Compiler runs, besides surtur's own include directory.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::util::{self, glob, log};
+
+use super::{
+    compiler::{cfg_flags, object_path, CompileCtx, Compiler, SourceKind, OBJ_DIR},
+    config::{Config, ProjType},
+};
+
+const GENERATED_NOTE: &str = "Generated by `surtur export`, changes are overwritten by the next export";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Make,
+    CMake,
+    Ninja,
+}
+
+impl ExportFormat {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(format: &str) -> Option<ExportFormat> {
+        match format {
+            "make" => Some(ExportFormat::Make),
+            "cmake" => Some(ExportFormat::CMake),
+            "ninja" => Some(ExportFormat::Ninja),
+            _ => None,
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ExportFormat::Make => "Makefile",
+            ExportFormat::CMake => "CMakeLists.txt",
+            ExportFormat::Ninja => "build.ninja",
+        }
+    }
+}
+
+/// A source file with the language it is compiled as
+struct Unit {
+    lang: &'static str,
+    src: String,
+    /// Object of the source, relative to the directory of objects
+    obj: String,
+}
+
+/// A static library, either a dependency or the project itself
+struct Archive {
+    name: String,
+    out: String,
+    obj_dir: String,
+    units: Vec<Unit>,
+    /// Pre-built objects that are put into the archive
+    objects: Vec<String>,
+    flags: Vec<String>,
+}
+
+impl Archive {
+    fn object(&self, unit: &Unit) -> String {
+        format!("{}/{}", self.obj_dir, unit.obj)
+    }
+}
+
+struct Executable {
+    name: String,
+    units: Vec<Unit>,
+    /// Pre-built objects and archives that are linked as they are
+    objects: Vec<String>,
+    flags: Vec<String>,
+    link_flags: Vec<String>,
+}
+
+enum Main {
+    Exe(Executable),
+    Lib(Archive),
+}
+
+/// The resolved build of a project
+struct BuildDesc {
+    name: String,
+    version: String,
+    cc: String,
+    build_dir: String,
+    deps: Vec<Archive>,
+    main: Main,
+}
+
+impl BuildDesc {
+    fn uses_asm(&self) -> bool {
+        let units = match &self.main {
+            Main::Exe(exe) => &exe.units,
+            Main::Lib(lib) => &lib.units,
+        };
+        units
+            .iter()
+            .chain(self.deps.iter().flat_map(|dep| dep.units.iter()))
+            .any(|unit| unit.lang != "c")
+    }
+}
+
+/// Paths inside of the project are relative, so the export works from anywhere
+fn rel_path(proj_dir: &Path, path: &Path) -> String {
+    if let Some(rel) = glob::relative(path, proj_dir) {
+        return if rel.is_empty() { ".".into() } else { rel };
+    }
+    // Projects next to this one, like workspace members
+    if let Some(parent) = proj_dir.parent() {
+        if let Some(rel) = glob::relative(path, parent) {
+            return format!("../{}", rel);
+        }
+    }
+    path.display().to_string()
+}
+
+fn units(files: HashSet<PathBuf>, root_dir: &Path, path: &dyn Fn(&Path) -> String) -> Vec<Unit> {
+    let mut files: Vec<PathBuf> = files.into_iter().collect();
+    files.sort();
+    files
+        .iter()
+        .map(|file| Unit {
+            lang: SourceKind::from_path(file).lang(),
+            src: path(file),
+            obj: glob::relative(&object_path(Path::new(""), root_dir, file), Path::new("")).unwrap_or_default(),
+        })
+        .collect()
+}
+
+fn describe(proj_dir: &Path, cfg: &Config) -> anyhow::Result<BuildDesc> {
+    let compiler = Compiler::new(proj_dir, cfg)?;
+    let path = |file: &Path| rel_path(proj_dir, file);
+    let target_dir = util::target_dir();
+    let build_dir = path(&target_dir);
+
+    let mut lib_flags = vec!["-w".to_string(), "-DNOTESTS".into(), compiler.std_flag()];
+    lib_flags.extend(compiler.include_flags(&path)?);
+
+    // Dependencies are built with the project's compiler and flags, just like build_deps does
+    let mut deps = Vec::new();
+    for pkg in compiler.packages() {
+        let dep_cfg = &pkg.cfg;
+        let dep_dir = &pkg.location;
+        let name = pkg.name.clone();
+        let sources = dep_cfg.active_sources();
+        let ctx = CompileCtx {
+            out_dir: &target_dir,
+            out_name: &name,
+            root_dir: dep_dir,
+            sources: &sources,
+            objects: &dep_cfg.objects,
+        };
+        deps.push(Archive {
+            out: format!("{}/{}/{}.a", build_dir, name, name),
+            obj_dir: format!("{}/{}/{}/{}.a", build_dir, name, OBJ_DIR, name),
+            units: units(compiler.lib_src_files(&ctx), dep_dir, &path),
+            objects: dep_cfg
+                .objects
+                .iter()
+                .filter(|object| object.extension().is_some_and(|ext| ext == "o"))
+                .map(|object| path(object))
+                .collect(),
+            flags: lib_flags
+                .iter()
+                .cloned()
+                .chain(cfg_flags(&dep_cfg.defines, &dep_cfg.include_dirs, &path))
+                .collect(),
+            name,
+        });
+    }
+
+    let root_name = compiler.root_name.to_string();
+    let sources = cfg.active_sources();
+    let ctx = CompileCtx {
+        out_dir: &target_dir,
+        out_name: &root_name,
+        root_dir: proj_dir,
+        sources: &sources,
+        objects: &cfg.objects,
+    };
+
+    let main = match cfg.props.proj_type {
+        ProjType::Bin => {
+            let mut flags = vec!["-DNOTESTS".to_string(), compiler.std_flag()];
+            flags.extend(compiler.cfg_flags(&path));
+            flags.extend(compiler.include_flags(&path)?);
+            Main::Exe(Executable {
+                units: units(compiler.src_files(&ctx), proj_dir, &path),
+                objects: cfg.objects.iter().map(|object| path(object)).collect(),
+                flags,
+                link_flags: compiler.link_flags(&target_dir, &path)?,
+                name: root_name,
+            })
+        }
+        ProjType::Lib => Main::Lib(Archive {
+            out: format!("{}/{}.a", build_dir, root_name),
+            obj_dir: format!("{}/{}/{}.a", build_dir, OBJ_DIR, root_name),
+            units: units(compiler.lib_src_files(&ctx), proj_dir, &path),
+            objects: cfg
+                .objects
+                .iter()
+                .filter(|object| object.extension().is_some_and(|ext| ext == "o"))
+                .map(|object| path(object))
+                .collect(),
+            flags: lib_flags.into_iter().chain(compiler.cfg_flags(&path)).collect(),
+            name: root_name,
+        }),
+    };
+
+    Ok(BuildDesc {
+        name: cfg.name.clone(),
+        version: cfg.props.proj_version.clone(),
+        cc: compiler.cmd().to_string(),
+        build_dir,
+        deps,
+        main,
+    })
+}
+
+/// Replaces the build directory at the start of the path with the variable
+fn with_build_var(desc: &BuildDesc, path: &str, var: &str) -> String {
+    match path.strip_prefix(&desc.build_dir) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", var, rest),
+        _ => path.to_string(),
+    }
+}
+
+/// Joins the flags for a shell, quoting the ones with special characters like defines of strings
+fn shell_words(flags: &[String]) -> String {
+    flags
+        .iter()
+        .map(|flag| log::shell_quote(flag.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Joins the flags as quoted CMake arguments
+fn cmake_words(flags: &[String]) -> String {
+    flags
+        .iter()
+        .map(|flag| format!("{:?}", flag))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Joins the parts with spaces, leaving out empty ones
+fn words(parts: &[&str]) -> String {
+    parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `-x c src/main.c -x c src/util.c`
+fn unit_args(units: &[Unit]) -> String {
+    units
+        .iter()
+        .map(|unit| format!("-x {} {}", unit.lang, unit.src))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Output directory and extra flags of every profile
+const PROFILES: [(&str, &str, &str); 3] = [
+    ("all", "", ""),
+    ("debug", "/debug", "-g"),
+    ("release", "/release", "-O3"),
+];
+
+fn render_make(desc: &BuildDesc) -> String {
+    let mut out = format!("# {}\n\nCC = {}\nAR = ar\nBUILD_DIR = {}\n\n", GENERATED_NOTE, desc.cc, desc.build_dir);
+    let build_dir = |path: &str| with_build_var(desc, path, "$(BUILD_DIR)");
+
+    let dep_archives: Vec<String> = desc.deps.iter().map(|dep| build_dir(&dep.out)).collect();
+    let render_archive = |out: &mut String, lib: &Archive| {
+        let objects: Vec<String> = lib.units.iter().map(|unit| build_dir(&lib.object(unit))).collect();
+        let inputs = words(&[&objects.join(" "), &lib.objects.join(" ")]);
+        let _ = writeln!(out, "{}: {}", build_dir(&lib.out), inputs);
+        let _ = writeln!(out, "\t@mkdir -p $(@D)\n\t$(AR) rcs $@ {}\n", inputs);
+        for (unit, object) in lib.units.iter().zip(&objects) {
+            let _ = writeln!(out, "{}: {}", object, unit.src);
+            let _ = writeln!(
+                out,
+                "\t@mkdir -p $(@D)\n\t$(CC) -c -x {} $< -o $@ {}\n",
+                unit.lang,
+                shell_words(&lib.flags)
+            );
+        }
+    };
+
+    match &desc.main {
+        Main::Exe(exe) => {
+            let _ = writeln!(out, "CFLAGS = {}", shell_words(&exe.flags));
+            let _ = writeln!(
+                out,
+                "LDFLAGS = {}\n",
+                exe.link_flags
+                    .iter()
+                    .map(|flag| match flag.strip_prefix("-L") {
+                        Some(dir) => format!("-L{}", build_dir(dir)),
+                        None => flag.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            let _ = writeln!(out, ".PHONY: all debug release clean\n");
+            for (profile, dir, _) in PROFILES {
+                let _ = writeln!(out, "{}: $(BUILD_DIR){}/{}", profile, dir, exe.name);
+            }
+            out.push('\n');
+            let srcs: Vec<&str> = exe.units.iter().map(|unit| unit.src.as_str()).collect();
+            for (_, dir, flags) in PROFILES {
+                let _ = writeln!(
+                    out,
+                    "$(BUILD_DIR){}/{}: {}",
+                    dir,
+                    exe.name,
+                    words(&[&srcs.join(" "), &exe.objects.join(" "), &dep_archives.join(" ")])
+                );
+                let cmd = words(&[
+                    "$(CC)",
+                    flags,
+                    &unit_args(&exe.units),
+                    "-x none",
+                    &exe.objects.join(" "),
+                    "-o $@ $(CFLAGS) $(LDFLAGS)",
+                ]);
+                let _ = writeln!(out, "\t@mkdir -p $(@D)\n\t{}\n", cmd);
+            }
+        }
+        Main::Lib(lib) => {
+            // Libraries are built the same way in every profile
+            let _ = writeln!(out, ".PHONY: all debug release clean\n");
+            for (profile, _, _) in PROFILES {
+                let _ = writeln!(out, "{}: {}", profile, words(&[&build_dir(&lib.out), &dep_archives.join(" ")]));
+            }
+            out.push('\n');
+            render_archive(&mut out, lib);
+        }
+    }
+
+    for dep in &desc.deps {
+        let _ = writeln!(out, "# Dependency {}", dep.name);
+        render_archive(&mut out, dep);
+    }
+
+    out.push_str("clean:\n\trm -rf $(BUILD_DIR)\n");
+    out
+}
+
+fn render_cmake(desc: &BuildDesc) -> String {
+    let languages = if desc.uses_asm() { "C ASM" } else { "C" };
+    let mut out = format!(
+        "# {}\ncmake_minimum_required(VERSION 3.13)\n\nif(NOT CMAKE_C_COMPILER)\n    set(CMAKE_C_COMPILER {})\nendif()\n\n",
+        GENERATED_NOTE, desc.cc
+    );
+    // CMake only accepts numeric versions
+    if desc.version.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        let _ = writeln!(out, "project({} VERSION {} LANGUAGES {})\n", desc.name, desc.version, languages);
+    } else {
+        let _ = writeln!(out, "project({} LANGUAGES {})\n", desc.name, languages);
+    }
+    out.push_str("# Same profiles as surtur, without the flags CMake adds on its own\nset(CMAKE_C_FLAGS_DEBUG \"-g\")\nset(CMAKE_C_FLAGS_RELEASE \"-O3\")\n\n");
+
+    let render_archive = |out: &mut String, lib: &Archive| {
+        let inputs: Vec<&str> = lib
+            .units
+            .iter()
+            .map(|unit| unit.src.as_str())
+            .chain(lib.objects.iter().map(String::as_str))
+            .collect();
+        let _ = writeln!(out, "add_library({} STATIC\n    {}\n)", lib.name, inputs.join("\n    "));
+        let _ = writeln!(out, "target_compile_options({} PRIVATE {})", lib.name, cmake_words(&lib.flags));
+        let _ = writeln!(out, "set_target_properties({} PROPERTIES PREFIX \"\")\n", lib.name);
+    };
+
+    for dep in &desc.deps {
+        let _ = writeln!(out, "# Dependency {}", dep.name);
+        render_archive(&mut out, dep);
+    }
+
+    match &desc.main {
+        Main::Exe(exe) => {
+            let srcs: Vec<&str> = exe.units.iter().map(|unit| unit.src.as_str()).collect();
+            let _ = writeln!(out, "add_executable({}\n    {}\n)", exe.name, srcs.join("\n    "));
+            let _ = writeln!(out, "target_compile_options({} PRIVATE {})", exe.name, cmake_words(&exe.flags));
+            // Dependencies are linked as targets instead of through the build directory
+            let mut libs: Vec<String> = desc.deps.iter().map(|dep| dep.name.clone()).collect();
+            libs.extend(exe.objects.iter().cloned());
+            libs.extend(
+                exe.link_flags
+                    .iter()
+                    .filter(|flag| !flag.starts_with("-L") && !flag.starts_with("-l:"))
+                    .map(|flag| flag.strip_prefix("-l").unwrap_or(flag).to_string()),
+            );
+            if !libs.is_empty() {
+                let _ = writeln!(out, "target_link_libraries({} PRIVATE {})", exe.name, libs.join(" "));
+            }
+        }
+        Main::Lib(lib) => render_archive(&mut out, lib),
+    }
+    out
+}
+
+fn render_ninja(desc: &BuildDesc) -> String {
+    let mut out = format!("# {}\n\ncc = {}\nar = ar\nbuilddir = {}\n\n", GENERATED_NOTE, desc.cc, desc.build_dir);
+    let build_dir = |path: &str| with_build_var(desc, path, "$builddir");
+    out.push_str(concat!(
+        "rule exe\n",
+        "  command = $cc $profile $srcs -x none $objects -o $out $cflags $ldflags\n",
+        "  description = Building $out\n\n",
+        "rule obj\n",
+        "  command = $cc -c -x $lang $in -o $out $flags\n",
+        "  description = Compiling $in\n\n",
+        "rule archive\n",
+        "  command = $ar rcs $out $in\n",
+        "  description = Archiving $out\n\n",
+    ));
+
+    let dep_archives: Vec<String> = desc.deps.iter().map(|dep| build_dir(&dep.out)).collect();
+    let render_archive = |out: &mut String, lib: &Archive| {
+        let objects: Vec<String> = lib.units.iter().map(|unit| build_dir(&lib.object(unit))).collect();
+        for (unit, object) in lib.units.iter().zip(&objects) {
+            let _ = writeln!(out, "build {}: obj {}\n  lang = {}\n  flags = {}", object, unit.src, unit.lang, shell_words(&lib.flags));
+        }
+        let inputs = words(&[&objects.join(" "), &lib.objects.join(" ")]);
+        let _ = writeln!(out, "build {}: archive {}\n", build_dir(&lib.out), inputs);
+    };
+
+    for dep in &desc.deps {
+        let _ = writeln!(out, "# Dependency {}", dep.name);
+        render_archive(&mut out, dep);
+    }
+
+    match &desc.main {
+        Main::Exe(exe) => {
+            let _ = writeln!(out, "cflags = {}", shell_words(&exe.flags));
+            let _ = writeln!(
+                out,
+                "ldflags = {}\n",
+                exe.link_flags
+                    .iter()
+                    .map(|flag| match flag.strip_prefix("-L") {
+                        Some(dir) => format!("-L{}", build_dir(dir)),
+                        None => flag.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            let srcs: Vec<&str> = exe.units.iter().map(|unit| unit.src.as_str()).collect();
+            // Dependencies are implicit inputs, they are not passed to the rule
+            let implicit = match dep_archives.is_empty() {
+                true => String::new(),
+                false => format!("| {}", dep_archives.join(" ")),
+            };
+            for (profile, dir, flags) in PROFILES {
+                let out_path = format!("$builddir{}/{}", dir, exe.name);
+                let _ = writeln!(
+                    out,
+                    "build {}: exe {}\n  srcs = {}\n  objects = {}\n  profile = {}",
+                    out_path,
+                    words(&[&srcs.join(" "), &exe.objects.join(" "), &implicit]),
+                    unit_args(&exe.units),
+                    exe.objects.join(" "),
+                    flags
+                );
+                if profile != "all" {
+                    let _ = writeln!(out, "build {}: phony {}", profile, out_path);
+                }
+                out.push('\n');
+            }
+            let _ = writeln!(out, "default $builddir/{}", exe.name);
+        }
+        Main::Lib(lib) => {
+            render_archive(&mut out, lib);
+            let _ = writeln!(out, "default {}", build_dir(&lib.out));
+        }
+    }
+    out
+}
+
+/// Writes the build of the project in the format into the project's
+/// root directory and returns the path of the written file
+pub fn export_proj(
+    proj_dir: &Path,
+    cfg: &Config,
+    format: ExportFormat,
+    output: Option<&Path>,
+    force: bool,
+) -> anyhow::Result<PathBuf> {
+    let path = match output {
+        Some(output) => output.to_path_buf(),
+        None => proj_dir.join(format.file_name()),
+    };
+    // Build files written by hand must not be lost
+    let is_generated = fs::read_to_string(&path).is_ok_and(|content| content.contains(GENERATED_NOTE));
+    if path.exists() && !is_generated && !force {
+        bail!("{} already exists, use --force to overwrite it", path.display());
+    }
+
+    let desc = describe(proj_dir, cfg)?;
+    let content = match format {
+        ExportFormat::Make => render_make(&desc),
+        ExportFormat::CMake => render_cmake(&desc),
+        ExportFormat::Ninja => render_ninja(&desc),
+    };
+
+    log::status("Exporting", path.display());
+    if !log::is_dry_run() {
+        fs::write(&path, content).context(format!("Failed to write {}", path.display()))?;
+    }
+    Ok(path)
+}
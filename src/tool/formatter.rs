@@ -0,0 +1,119 @@
+//! Handling of formatting the project's c files
+//! with clang-format. The style is taken from the
+//! project's .clang-format file.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context};
+
+use crate::util::{self, glob, log};
+
+use super::config::Config;
+
+pub const CLANG_FORMAT_FILE: &str = ".clang-format";
+
+/// Directories of the project whose files are formatted
+const FORMAT_DIRS: [&str; 4] = ["src", "include", "tests", "examples"];
+
+/// Markers of generated files, only the start of a file is searched
+const GENERATED_MARKERS: [&str; 2] = ["@generated", "DO NOT EDIT"];
+
+const GENERATED_MARKER_LINES: usize = 5;
+
+/// Checks if the file says that it was generated
+fn is_generated(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|content| {
+        content
+            .lines()
+            .take(GENERATED_MARKER_LINES)
+            .any(|line| GENERATED_MARKERS.iter().any(|marker| line.contains(marker)))
+    })
+}
+
+/// All c and header files of the project that are not excluded or generated
+pub fn format_files(proj_dir: &Path, cfg: &Config) -> Vec<PathBuf> {
+    let target_dir = util::target_dir();
+    let mut files: Vec<PathBuf> = FORMAT_DIRS
+        .iter()
+        .flat_map(|dir| glob::find(proj_dir, &format!("{}/**", dir)))
+        .filter(|file| file.extension().is_some_and(|ext| ext == "c" || ext == "h"))
+        .filter(|file| !cfg.excluded.contains(file) && !file.starts_with(&target_dir))
+        .filter(|file| !is_generated(file))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Formats the project's files in place or only checks
+/// if they are formatted when `check` is set
+pub fn format_proj(proj_dir: &Path, cfg: &Config, check: bool) -> anyhow::Result<()> {
+    if !proj_dir.join(CLANG_FORMAT_FILE).exists() {
+        log::warn(format!(
+            "No {} found in {}, using clang-format's default style",
+            CLANG_FORMAT_FILE,
+            proj_dir.display()
+        ));
+    }
+
+    let files = format_files(proj_dir, cfg);
+    let cmds = files
+        .iter()
+        .map(|file| {
+            let mut cmd = Command::new("clang-format");
+            cmd.arg("--style=file");
+            if check {
+                cmd.args(["--dry-run", "-Werror"]);
+            } else {
+                cmd.arg("-i");
+            }
+            cmd.arg(file);
+            cmd
+        })
+        .collect();
+
+    let action = if check { "Checking" } else { "Formatting" };
+    log::status(action, format!("{} ({} files)", cfg.name, files.len()));
+
+    let unformatted = Mutex::new(Vec::new());
+    let failed = Mutex::new(Vec::new());
+    util::exec_parallel_with(cmds, |i, output| {
+        if output.status.success() {
+            return;
+        }
+        log::verbose(String::from_utf8_lossy(&output.stderr).trim_end());
+        if check {
+            unformatted.lock().unwrap().push(&files[i]);
+        } else {
+            failed.lock().unwrap().push(output.stderr);
+        }
+    })
+    .context("Failed to run clang-format, is it installed?")?;
+
+    let failed = failed.into_inner().unwrap();
+    for stderr in &failed {
+        eprint!("{}", String::from_utf8_lossy(stderr));
+    }
+    if !failed.is_empty() {
+        bail!("Failed to format {} file(s) of `{}`", failed.len(), cfg.name);
+    }
+
+    let mut unformatted = unformatted.into_inner().unwrap();
+    if unformatted.is_empty() {
+        return Ok(());
+    }
+    unformatted.sort();
+    for file in &unformatted {
+        let file = glob::relative(file, proj_dir).unwrap_or_else(|| file.display().to_string());
+        println!("{}", file);
+    }
+    bail!(
+        "{} file(s) of `{}` are not formatted, run `surtur fmt` to format them",
+        unformatted.len(),
+        cfg.name
+    )
+}
@@ -0,0 +1,352 @@
+//! Build graph of a project. Every step of a build, like
+//! build scripts, compiling, archiving, linking and building
+//! dependency projects, is a node. The edges say which nodes
+//! have to finish first, nodes without open edges run in parallel.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{self, Write},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex,
+    },
+    thread,
+};
+
+use anyhow::{bail, Context};
+
+use crate::util::{self, log};
+
+pub type NodeId = usize;
+
+static KEEP_GOING: AtomicBool = AtomicBool::new(false);
+
+static PLAN_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// `keep_going` builds everything that doesn't depend on a failed node,
+/// `plan_only` prints the graph instead of running it
+pub fn init(keep_going: bool, plan_only: bool) {
+    KEEP_GOING.store(keep_going, Ordering::Relaxed);
+    PLAN_ONLY.store(plan_only, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_plan_only() -> bool {
+    PLAN_ONLY.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Script,
+    Compile,
+    Archive,
+    Link,
+    /// All steps of a dependency project
+    Dependency,
+}
+
+impl NodeKind {
+    /// Status that is reported when a node of the kind starts
+    fn status(&self) -> Option<&'static str> {
+        match self {
+            NodeKind::Compile => Some("Compiling"),
+            NodeKind::Archive => Some("Archiving"),
+            NodeKind::Link => Some("Building"),
+            // Scripts report themselves and dependencies only group other nodes
+            NodeKind::Script | NodeKind::Dependency => None,
+        }
+    }
+}
+
+impl Display for NodeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NodeKind::Script => "script",
+            NodeKind::Compile => "compile",
+            NodeKind::Archive => "archive",
+            NodeKind::Link => "link",
+            NodeKind::Dependency => "dependency",
+        })
+    }
+}
+
+pub enum Action<'g> {
+    /// Nothing to do, the node only groups the nodes it depends on
+    None,
+    Command(Command),
+    /// Work surtur does itself, like running lua build scripts
+    Task(Box<dyn FnOnce() -> anyhow::Result<()> + Send + 'g>),
+}
+
+struct Node<'g> {
+    kind: NodeKind,
+    label: String,
+    action: Action<'g>,
+    deps: Vec<NodeId>,
+}
+
+#[derive(Default)]
+pub struct BuildGraph<'g> {
+    nodes: Vec<Node<'g>>,
+}
+
+/// Progress of running a graph, shared between the workers
+struct Schedule {
+    ready: VecDeque<NodeId>,
+    /// Number of unfinished dependencies of every node
+    waiting: Vec<usize>,
+    running: usize,
+    finished: usize,
+    failed: Vec<anyhow::Error>,
+    stopped: bool,
+}
+
+impl<'g> BuildGraph<'g> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node that runs after all of `deps`. Nodes can only
+    /// depend on nodes that were added before them, so there are no cycles
+    pub fn add(&mut self, kind: NodeKind, label: impl Into<String>, action: Action<'g>, deps: &[NodeId]) -> NodeId {
+        let id = self.nodes.len();
+        debug_assert!(deps.iter().all(|dep| *dep < id), "Nodes can only depend on earlier nodes");
+        self.nodes.push(Node {
+            kind,
+            label: label.into(),
+            action,
+            deps: deps.to_vec(),
+        });
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn print_plan(&self, name: &str) {
+        println!("Build plan of {} ({} steps)", name, self.nodes.len());
+        for (id, node) in self.nodes.iter().enumerate() {
+            let mut line = format!("{:>4}  {:<10} {}", id + 1, node.kind.to_string(), node.label);
+            if !node.deps.is_empty() {
+                let deps: Vec<String> = node.deps.iter().map(|dep| (dep + 1).to_string()).collect();
+                line.push_str(&format!(" (after {})", deps.join(", ")));
+            }
+            println!("{}", line);
+            if let (Action::Command(cmd), true) = (&node.action, log::verbosity() >= 1) {
+                println!("{:>18}{}", "", log::shell_command(cmd));
+            }
+        }
+    }
+
+    /// Runs all nodes in the order of their edges, independent nodes in parallel.
+    /// Stops at the first failure unless surtur was told to keep going
+    pub fn run(self, name: &str) -> anyhow::Result<()> {
+        if is_plan_only() {
+            self.print_plan(name);
+            return Ok(());
+        }
+        self.schedule(name, KEEP_GOING.load(Ordering::Relaxed))
+    }
+
+    fn schedule(self, name: &str, keep_going: bool) -> anyhow::Result<()> {
+        let count = self.nodes.len();
+
+        let mut dependents = vec![Vec::new(); count];
+        let mut waiting = vec![0; count];
+        let mut actions = Vec::with_capacity(count);
+        let mut nodes = Vec::with_capacity(count);
+        for (id, node) in self.nodes.into_iter().enumerate() {
+            waiting[id] = node.deps.len();
+            for dep in &node.deps {
+                dependents[*dep].push(id);
+            }
+            actions.push(Mutex::new(Some(node.action)));
+            nodes.push((node.kind, node.label));
+        }
+
+        let schedule = Mutex::new(Schedule {
+            ready: (0..count).filter(|id| waiting[*id] == 0).collect(),
+            waiting,
+            running: 0,
+            finished: 0,
+            failed: Vec::new(),
+            stopped: false,
+        });
+        let changed = Condvar::new();
+
+        let work = || loop {
+            let id = {
+                let mut schedule = schedule.lock().unwrap();
+                loop {
+                    if schedule.stopped {
+                        return;
+                    }
+                    if let Some(id) = schedule.ready.pop_front() {
+                        schedule.running += 1;
+                        break id;
+                    }
+                    // Nothing is running that could make more nodes ready
+                    if schedule.running == 0 {
+                        return;
+                    }
+                    schedule = changed.wait(schedule).unwrap();
+                }
+            };
+
+            let (kind, label) = &nodes[id];
+            let action = actions[id].lock().unwrap().take();
+            let result = run_node(*kind, label, action);
+
+            let mut schedule = schedule.lock().unwrap();
+            schedule.running -= 1;
+            match result {
+                Ok(()) => {
+                    schedule.finished += 1;
+                    for next in &dependents[id] {
+                        schedule.waiting[*next] -= 1;
+                        if schedule.waiting[*next] == 0 {
+                            schedule.ready.push_back(*next);
+                        }
+                    }
+                }
+                // Nodes that depend on the failed one never become ready
+                Err(err) => {
+                    schedule.failed.push(err);
+                    schedule.stopped = !keep_going;
+                }
+            }
+            changed.notify_all();
+        };
+
+        thread::scope(|scope| {
+            for _ in 0..util::jobs().min(count) {
+                scope.spawn(work);
+            }
+        });
+
+        let schedule = schedule.into_inner().unwrap();
+        let mut failed = schedule.failed;
+        match failed.len() {
+            0 => Ok(()),
+            1 if !keep_going => Err(failed.remove(0)),
+            _ => {
+                for err in &failed {
+                    eprintln!("{:#}", err);
+                }
+                bail!(
+                    "{} step(s) of building {} failed, {} step(s) were skipped",
+                    failed.len(),
+                    name,
+                    count - schedule.finished - failed.len()
+                )
+            }
+        }
+    }
+}
+
+fn run_node(kind: NodeKind, label: &str, action: Option<Action>) -> anyhow::Result<()> {
+    if let Some(status) = kind.status() {
+        log::status(status, label);
+    }
+    match action {
+        Some(Action::Command(mut cmd)) => exec_captured(&mut cmd).context(format!("Failed to {} {}", kind, label)),
+        Some(Action::Task(task)) => task(),
+        Some(Action::None) | None => Ok(()),
+    }
+}
+
+/// Runs the command of a node. Its output is printed at once when it
+/// finishes, so diagnostics of parallel nodes don't get mixed up
+fn exec_captured(cmd: &mut Command) -> anyhow::Result<()> {
+    log::command(cmd);
+    if log::is_dry_run() {
+        return Ok(());
+    }
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let output = cmd.output().context(format!("Failed to run `{}`", program))?;
+    let _ = io::stdout().write_all(&output.stdout);
+    let _ = io::stderr().write_all(&output.stderr);
+    if !output.status.success() {
+        bail!("`{}` failed with {}", program, output.status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    type Log = Arc<Mutex<Vec<NodeId>>>;
+
+    /// Task that records its id when it runs
+    fn record(log: &Log, id: NodeId) -> Action<'static> {
+        let log = Arc::clone(log);
+        Action::Task(Box::new(move || {
+            log.lock().unwrap().push(id);
+            Ok(())
+        }))
+    }
+
+    fn fail() -> Action<'static> {
+        Action::Task(Box::new(|| bail!("step failed")))
+    }
+
+    fn position(log: &Log, id: NodeId) -> usize {
+        log.lock().unwrap().iter().position(|found| *found == id).unwrap()
+    }
+
+    #[test]
+    fn nodes_run_after_their_dependencies() {
+        let log = Log::default();
+        let mut graph = BuildGraph::new();
+        let a = graph.add(NodeKind::Script, "a", record(&log, 0), &[]);
+        let b = graph.add(NodeKind::Script, "b", record(&log, 1), &[]);
+        let c = graph.add(NodeKind::Script, "c", record(&log, 2), &[a]);
+        let d = graph.add(NodeKind::Script, "d", Action::None, &[b, c]);
+        graph.add(NodeKind::Script, "e", record(&log, 4), &[d]);
+        graph.schedule("test", false).unwrap();
+
+        assert_eq!(log.lock().unwrap().len(), 4);
+        assert!(position(&log, 0) < position(&log, 2));
+        assert!(position(&log, 1) < position(&log, 4));
+        assert!(position(&log, 2) < position(&log, 4));
+    }
+
+    #[test]
+    fn failure_stops_dependents() {
+        let log = Log::default();
+        let mut graph = BuildGraph::new();
+        let failed = graph.add(NodeKind::Script, "failed", fail(), &[]);
+        let after = graph.add(NodeKind::Script, "after", record(&log, 1), &[failed]);
+        graph.add(NodeKind::Script, "last", record(&log, 2), &[after]);
+
+        let err = graph.schedule("test", false).unwrap_err();
+        assert_eq!(err.to_string(), "step failed");
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keep_going_runs_independent_nodes() {
+        let log = Log::default();
+        let mut graph = BuildGraph::new();
+        let failed = graph.add(NodeKind::Script, "failed", fail(), &[]);
+        graph.add(NodeKind::Script, "after", record(&log, 1), &[failed]);
+        let other = graph.add(NodeKind::Script, "other", record(&log, 2), &[]);
+        graph.add(NodeKind::Script, "other after", record(&log, 3), &[other]);
+
+        let err = graph.schedule("test", true).unwrap_err();
+        assert_eq!(err.to_string(), "1 step(s) of building test failed, 1 step(s) were skipped");
+        let mut ran = log.lock().unwrap().clone();
+        ran.sort();
+        assert_eq!(ran, [2, 3]);
+    }
+}
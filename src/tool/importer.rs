@@ -0,0 +1,1477 @@
+//! Handling of importing projects that are built with
+//! Make or CMake. The build is read from a compile database
+//! (compile_commands.json) if there is one, otherwise simple
+//! Makefiles and CMakeLists are parsed. Flags that have no
+//! equivalent in project.lua are reported instead of dropped.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env,
+    fmt::Write as _,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+use crate::util::{self, glob, log, lua_utils::lua_string, CONFIG_FILE, DEFAULT_COMPILER};
+
+use super::{compiler::Standard, config::ProjType, creator::Project};
+
+pub const COMPILE_DB_FILE: &str = "compile_commands.json";
+
+/// Directories of the project compile databases are usually written to
+const COMPILE_DB_DIRS: [&str; 3] = ["", "build", "cmake-build-debug"];
+
+/// Compilers that are recognized in the recipes of Makefiles
+const COMPILERS: [&str; 4] = ["cc", "gcc", "clang", "tcc"];
+
+/// Make variables that usually hold compiler or linker flags
+const MAKE_FLAG_VARS: [&str; 7] = ["INCLUDES", "INCLUDE", "INC", "INCS", "DEFINES", "DEFS", "LDLIBS"];
+
+/// Make variables that usually hold the name of the program
+const MAKE_NAME_VARS: [&str; 7] = ["TARGET", "TARGET_EXEC", "EXEC", "BIN", "PROG", "PROGRAM", "NAME"];
+
+/// Variables are expanded at most this deep, Makefiles can reference themselves
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Make,
+    CMake,
+}
+
+impl ImportFormat {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(format: &str) -> Option<ImportFormat> {
+        match format {
+            "make" => Some(ImportFormat::Make),
+            "cmake" => Some(ImportFormat::CMake),
+            _ => None,
+        }
+    }
+
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ImportFormat::Make => "Makefile",
+            ImportFormat::CMake => "CMakeLists.txt",
+        }
+    }
+
+    /// Build file of the format in the directory, if there is one
+    fn build_file(&self, root: &Path) -> Option<PathBuf> {
+        let names: &[&str] = match self {
+            ImportFormat::Make => &["GNUmakefile", "makefile", "Makefile"],
+            ImportFormat::CMake => &["CMakeLists.txt"],
+        };
+        names.iter().map(|name| root.join(name)).find(|file| file.is_file())
+    }
+}
+
+/// Everything that was found out about the build of a project
+#[derive(Debug, Default)]
+struct ImportedBuild {
+    name: Option<String>,
+    version: Option<String>,
+    compiler: Option<String>,
+    std: Option<Standard>,
+    proj_type: Option<ProjType>,
+    sources: BTreeSet<PathBuf>,
+    defines: Vec<String>,
+    include_dirs: Vec<PathBuf>,
+    libraries: Vec<String>,
+    /// Additional executables, mapped from their name to their entry file
+    binaries: BTreeMap<String, PathBuf>,
+    /// Flags surtur has no equivalent for
+    ignored: BTreeSet<String>,
+}
+
+impl ImportedBuild {
+    fn add_source(&mut self, file: PathBuf) {
+        if util::is_src_file(&file) && file.is_file() {
+            self.sources.insert(file);
+        }
+    }
+
+    fn add_library(&mut self, lib: &str) {
+        let lib = lib.strip_prefix("-l").unwrap_or(lib);
+        push_unique(&mut self.libraries, lib.to_string());
+    }
+
+    /// Takes the standard, defines, include directories and libraries
+    /// from the arguments of a compiler, paths are relative to `base`
+    fn add_flags(&mut self, args: &[String], base: &Path) {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            // Flags that are followed by their value
+            if let Some(flag) = ["-D", "-I", "-isystem", "-iquote", "-idirafter", "-l", "-U"]
+                .into_iter()
+                .find(|flag| arg == flag)
+            {
+                if let Some(value) = args.next() {
+                    self.add_flags(&[format!("{}{}", flag, value)], base);
+                }
+                continue;
+            }
+
+            if let Some(define) = arg.strip_prefix("-D") {
+                push_unique(&mut self.defines, define.to_string());
+            } else if let Some(dir) = ["-isystem", "-iquote", "-idirafter", "-I"]
+                .into_iter()
+                .find_map(|flag| arg.strip_prefix(flag))
+            {
+                push_unique(&mut self.include_dirs, normalize(&base.join(dir)));
+            } else if let Some(lib) = arg.strip_prefix("-l") {
+                self.add_library(lib);
+            } else if arg == "-pthread" {
+                self.add_library("pthread");
+            } else if let Some(std) = arg.strip_prefix("-std=") {
+                match parse_std(std) {
+                    Some(std) => self.std = Some(std),
+                    None => {
+                        self.ignored.insert(arg.clone());
+                    }
+                }
+            } else if ["-o", "-MF", "-MT", "-MQ", "-x"].contains(&arg.as_str()) {
+                args.next();
+            } else if ["-L", "-include", "-imacros"].contains(&arg.as_str()) {
+                if let Some(value) = args.next() {
+                    self.ignored.insert(format!("{} {}", arg, value));
+                }
+            } else if arg == "-c"
+                || arg == "-w"
+                || arg.starts_with("-g")
+                || arg.starts_with("-O")
+                || arg.starts_with("-W")
+                || arg.starts_with("-M")
+            {
+                // Profiles and warnings are handled by surtur
+            } else if arg.starts_with('-') {
+                self.ignored.insert(arg.clone());
+            }
+        }
+    }
+
+    /// The compile database knows the exact sources and flags of every file,
+    /// only what can't be found in it is taken from the build file
+    fn merge_compile_db(&mut self, db: ImportedBuild) {
+        if !db.sources.is_empty() {
+            self.sources = db.sources;
+            self.defines = db.defines;
+            self.include_dirs = db.include_dirs;
+        }
+        self.std = db.std.or(self.std);
+        self.compiler = self.compiler.take().or(db.compiler);
+        for lib in db.libraries {
+            push_unique(&mut self.libraries, lib);
+        }
+        self.ignored.extend(db.ignored);
+    }
+
+    /// Picks the main file of the project, other files with
+    /// a main function become binaries of the project
+    fn split_binaries(&mut self) {
+        let mut mains: Vec<PathBuf> = self.sources.iter().filter(|file| has_main(file)).cloned().collect();
+        let proj_type = *self.proj_type.get_or_insert(match mains.is_empty() {
+            true => ProjType::Lib,
+            false => ProjType::Bin,
+        });
+        if proj_type == ProjType::Bin {
+            // Keep main.c if there is one, otherwise the first file with a main function
+            let main = mains
+                .iter()
+                .position(|file| file.file_name().is_some_and(|name| name == "main.c"))
+                .unwrap_or(0);
+            mains.remove(main);
+        }
+
+        for file in mains {
+            self.sources.remove(&file);
+            // Libraries leave out src/main.c on their own
+            if proj_type == ProjType::Lib {
+                log::verbose(format!("Leaving out {} from the library, it has a main function", file.display()));
+                continue;
+            }
+            let name = file.file_stem().unwrap().to_string_lossy().to_string();
+            log::verbose(format!("Importing {} as binary `{}`", file.display(), name));
+            self.binaries.entry(name).or_insert(file);
+        }
+    }
+
+    /// Generates the project.lua of the imported build
+    fn layout(&self, root: &Path, name: &str, origin: &str) -> String {
+        let rel = |path: &Path| glob::relative(path, root).unwrap_or_else(|| path.display().to_string());
+        let proj_type = self.proj_type.unwrap_or(ProjType::Bin);
+
+        let mut content = format!(
+            concat!(
+                "-- imported from {} by `surtur init`\n",
+                "-- properties\n",
+                "Name = {}\n",
+                "Props = {{\n",
+                "    std = {},\n",
+                "    version = {},\n",
+                "    type = {},\n",
+                "    compiler = {},\n",
+                "}}\n",
+            ),
+            origin,
+            lua_string(self.name.as_deref().unwrap_or(name)),
+            // Compilers default to a gnu standard, which legacy code tends to rely on
+            lua_string(&self.std.unwrap_or(Standard::Gnu17).to_string()),
+            lua_string(self.version.as_deref().unwrap_or("0.1")),
+            lua_string(&proj_type.to_string()),
+            lua_string(self.compiler.as_deref().unwrap_or(DEFAULT_COMPILER)),
+        );
+
+        let mut write_list = |key: &str, comment: &str, items: Vec<String>| {
+            if items.is_empty() {
+                return;
+            }
+            let _ = writeln!(content, "\n-- {}\n{} = {{", comment, key);
+            for item in items {
+                let _ = writeln!(content, "    {},", lua_string(&item));
+            }
+            content.push_str("}\n");
+        };
+
+        let src_dir = root.join("src");
+        if self.sources.iter().all(|file| file.starts_with(&src_dir)) {
+            // Every file in src is compiled by default, so only the unused ones have to be excluded
+            let bin_dir = src_dir.join("bin");
+            let mut excluded: Vec<String> = util::get_src_files(&src_dir)
+                .into_iter()
+                .filter(|file| {
+                    !self.sources.contains(file)
+                        && !self.binaries.values().any(|bin| bin == file)
+                        && !file.starts_with(&bin_dir)
+                })
+                .filter_map(|file| glob::relative(&file, &src_dir))
+                .collect();
+            // Libraries leave out main.c on their own
+            if proj_type == ProjType::Lib {
+                excluded.retain(|file| file != "main.c");
+            }
+            excluded.sort();
+            write_list("Exclude", "source files that are not part of the build", excluded);
+        } else {
+            let sources = self.sources.iter().map(|file| rel(file)).collect();
+            write_list("Sources", "source files of the build", sources);
+        }
+
+        write_list("Defines", "preprocessor definitions", self.defines.clone());
+        write_list(
+            "IncludeDirs",
+            "directories searched for headers",
+            self.include_dirs.iter().map(|dir| rel(dir)).collect(),
+        );
+        write_list("Libraries", "linked system libraries", self.libraries.clone());
+
+        if !self.binaries.is_empty() {
+            content.push_str("\n-- additional executables\nBinaries = {\n");
+            for (name, file) in &self.binaries {
+                let _ = writeln!(content, "    [{}] = {},", lua_string(name), lua_string(&rel(file)));
+            }
+            content.push_str("}\n");
+        }
+
+        content.push_str("\n-- external dependencies\nDependencies = {\n}\n");
+        content
+    }
+}
+
+fn push_unique<T: PartialEq>(items: &mut Vec<T>, item: T) {
+    if !items.contains(&item) {
+        items.push(item);
+    }
+}
+
+/// Resolves `.` and `..` without touching the file system
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            comp => normalized.push(comp),
+        }
+    }
+    normalized
+}
+
+fn has_main(file: &Path) -> bool {
+    fs::read_to_string(file).is_ok_and(|content| {
+        content.lines().any(|line| {
+            let line = line.trim_start();
+            line.starts_with("int main(") || line.starts_with("int main (") || line.starts_with("main(")
+        })
+    })
+}
+
+/// Standards of -std, including the aliases gcc accepts
+fn parse_std(std: &str) -> Option<Standard> {
+    let std = match std {
+        "c90" | "iso9899:1990" => "c89",
+        "gnu90" => "gnu89",
+        "c9x" | "iso9899:1999" => "c99",
+        "gnu9x" => "gnu99",
+        "c1x" | "iso9899:2011" => "c11",
+        "gnu1x" => "gnu11",
+        "c18" | "iso9899:2017" | "iso9899:2018" => "c17",
+        "gnu18" => "gnu17",
+        std => std,
+    };
+    Standard::from_str(std)
+}
+
+/// Splits a command line into its words the way a shell does
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+            }
+            (Some(_), c) => word.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Looks for a compile database in the project
+fn find_compile_db(root: &Path) -> Option<PathBuf> {
+    COMPILE_DB_DIRS
+        .iter()
+        .map(|dir| root.join(dir).join(COMPILE_DB_FILE))
+        .find(|file| file.is_file())
+}
+
+fn import_compile_db(root: &Path, file: &Path) -> anyhow::Result<ImportedBuild> {
+    let content = fs::read_to_string(file).context(format!("Failed to read {}", file.display()))?;
+    let db: Value = serde_json::from_str(&content).context(format!("Failed to parse {}", file.display()))?;
+    let entries = db
+        .as_array()
+        .context(format!("{} has to contain a list of commands", file.display()))?;
+
+    let mut build = ImportedBuild::default();
+    for entry in entries {
+        let dir = entry["directory"].as_str().map(PathBuf::from).unwrap_or_else(|| root.to_path_buf());
+        let src = entry["file"]
+            .as_str()
+            .context(format!("Every command in {} requires a `file`", file.display()))?;
+        let args: Vec<String> = match entry["arguments"].as_array() {
+            Some(args) => args.iter().filter_map(|arg| arg.as_str().map(String::from)).collect(),
+            None => split_words(
+                entry["command"]
+                    .as_str()
+                    .context(format!("Every command in {} requires `arguments` or a `command`", file.display()))?,
+            ),
+        };
+
+        let src = normalize(&dir.join(src));
+        if !src.starts_with(root) {
+            log::verbose(format!("Skipping {}, it is outside of the project", src.display()));
+            continue;
+        }
+        build.add_source(src);
+        if let Some((cc, flags)) = args.split_first() {
+            if build.compiler.is_none() {
+                build.compiler = Path::new(cc).file_name().map(|name| name.to_string_lossy().to_string());
+            }
+            build.add_flags(flags, &dir);
+        }
+    }
+    Ok(build)
+}
+
+/// The variables and rules of a Makefile
+struct Makefile<'m> {
+    root: &'m Path,
+    vars: HashMap<String, String>,
+    /// Prerequisites of every rule
+    prerequisites: Vec<String>,
+    /// Targets of every rule
+    targets: Vec<String>,
+    recipes: Vec<String>,
+}
+
+impl<'m> Makefile<'m> {
+    fn parse(root: &'m Path, content: &str) -> Self {
+        let mut make = Self {
+            root,
+            vars: HashMap::new(),
+            prerequisites: Vec::new(),
+            targets: Vec::new(),
+            recipes: Vec::new(),
+        };
+        let content = content.replace("\\\r\n", " ").replace("\\\n", " ");
+        let mut in_define = false;
+
+        for line in content.lines() {
+            if in_define {
+                in_define = line.trim() != "endef";
+                continue;
+            }
+            if let Some(recipe) = line.strip_prefix('\t') {
+                make.recipes.push(recipe.to_string());
+                continue;
+            }
+            // Comments end the line, unless the # is escaped
+            let line = match line.find('#') {
+                Some(pos) if !line[..pos].ends_with('\\') => &line[..pos],
+                _ => line,
+            };
+            let line = line.trim();
+            let Some(first) = line.split_whitespace().next() else {
+                continue;
+            };
+            match first {
+                "define" => {
+                    in_define = true;
+                    continue;
+                }
+                // Both branches of conditionals are read, included files are not
+                "ifeq" | "ifneq" | "ifdef" | "ifndef" | "else" | "endif" | "include" | "-include"
+                | "sinclude" | "vpath" | "unexport" => continue,
+                _ => {}
+            }
+            let line = line
+                .strip_prefix("export ")
+                .or_else(|| line.strip_prefix("override "))
+                .unwrap_or(line);
+
+            if make.assign(line) {
+                continue;
+            }
+            if let Some((targets, prerequisites)) = line.split_once(':') {
+                let (prerequisites, recipe) = match prerequisites.split_once(';') {
+                    Some((prerequisites, recipe)) => (prerequisites, Some(recipe)),
+                    None => (prerequisites, None),
+                };
+                make.targets.push(targets.to_string());
+                make.prerequisites.push(prerequisites.to_string());
+                if let Some(recipe) = recipe {
+                    make.recipes.push(recipe.to_string());
+                }
+            }
+        }
+        make
+    }
+
+    /// Handles the line if it assigns a variable
+    fn assign(&mut self, line: &str) -> bool {
+        let Some(eq) = line.find('=') else {
+            return false;
+        };
+        let (lhs, value) = (&line[..eq], line[eq + 1..].trim());
+        let (name, op) = match lhs.trim_end() {
+            lhs if lhs.ends_with("::") => (&lhs[..lhs.len() - 2], ":"),
+            lhs if lhs.ends_with([':', '+', '?', '!']) => (&lhs[..lhs.len() - 1], &lhs[lhs.len() - 1..]),
+            lhs => (lhs, "="),
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) || name.contains(':') {
+            return false;
+        }
+
+        match op {
+            ":" => {
+                let value = self.expand(value, 0);
+                self.vars.insert(name.into(), value);
+            }
+            "+" => {
+                let var = self.vars.entry(name.into()).or_default();
+                if !var.is_empty() {
+                    var.push(' ');
+                }
+                var.push_str(value);
+            }
+            "?" => {
+                self.vars.entry(name.into()).or_insert_with(|| value.into());
+            }
+            "!" => log::verbose(format!("Ignoring shell assignment of `{}`", name)),
+            _ => {
+                self.vars.insert(name.into(), value.into());
+            }
+        }
+        true
+    }
+
+    fn variable(&self, name: &str, depth: usize) -> String {
+        match (self.vars.get(name), name) {
+            (Some(value), _) => self.expand(value, depth + 1),
+            (None, "CC") => "cc".into(),
+            (None, "AR") => "ar".into(),
+            (None, _) => String::new(),
+        }
+    }
+
+    /// Expands all variable references and functions of the text
+    fn expand(&self, text: &str, depth: usize) -> String {
+        if depth > MAX_EXPANSION_DEPTH {
+            return String::new();
+        }
+        let bytes = text.as_bytes();
+        let mut out = String::new();
+        let mut i = 0;
+        while i < text.len() {
+            let c = text[i..].chars().next().unwrap();
+            if c != '$' {
+                out.push(c);
+                i += c.len_utf8();
+                continue;
+            }
+            match text[i + 1..].chars().next() {
+                None => break,
+                Some('$') => {
+                    out.push('$');
+                    i += 2;
+                }
+                Some(open @ ('(' | '{')) => {
+                    let close = if open == '(' { b')' } else { b'}' };
+                    let mut level = 1;
+                    let mut end = i + 2;
+                    while end < bytes.len() {
+                        if bytes[end] == open as u8 {
+                            level += 1;
+                        } else if bytes[end] == close {
+                            level -= 1;
+                            if level == 0 {
+                                break;
+                            }
+                        }
+                        end += 1;
+                    }
+                    out.push_str(&self.reference(&text[i + 2..end.min(text.len())], depth));
+                    i = end + 1;
+                }
+                // Single character variables like $@ belong to recipes
+                Some(name) => {
+                    out.push_str(&self.variable(&name.to_string(), depth));
+                    i += 1 + name.len_utf8();
+                }
+            }
+        }
+        out
+    }
+
+    /// Expands the inside of $(...)
+    fn reference(&self, inner: &str, depth: usize) -> String {
+        if let Some((func, args)) = inner.split_once(char::is_whitespace) {
+            if func.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+                return self.function(func, args.trim_start(), depth);
+            }
+        }
+        // Substitution references like $(SRCS:.c=.o)
+        if let Some((name, subst)) = inner.split_once(':') {
+            if let Some((from, to)) = subst.split_once('=') {
+                let (from, to) = match from.contains('%') {
+                    true => (from.to_string(), to.to_string()),
+                    false => (format!("%{}", from), format!("%{}", to)),
+                };
+                return patsubst(&from, &to, &self.variable(name, depth));
+            }
+        }
+        self.variable(&self.expand(inner, depth + 1), depth)
+    }
+
+    fn function(&self, func: &str, args: &str, depth: usize) -> String {
+        let args: Vec<String> = split_args(args)
+            .into_iter()
+            .map(|arg| self.expand(arg, depth + 1))
+            .collect();
+        let arg = |i: usize| args.get(i).map(String::as_str).unwrap_or("");
+        let map_words = |text: &str, f: &dyn Fn(&str) -> String| {
+            text.split_whitespace().map(f).collect::<Vec<_>>().join(" ")
+        };
+
+        match func {
+            "wildcard" => {
+                let mut files: Vec<String> = arg(0)
+                    .split_whitespace()
+                    .flat_map(|pattern| glob::find(self.root, pattern))
+                    .filter_map(|file| glob::relative(&file, self.root))
+                    .collect();
+                files.sort();
+                files.join(" ")
+            }
+            "patsubst" => patsubst(arg(0), arg(1), arg(2)),
+            "subst" => arg(2).replace(arg(0), arg(1)),
+            "addprefix" => map_words(arg(1), &|word| format!("{}{}", arg(0), word)),
+            "addsuffix" => map_words(arg(1), &|word| format!("{}{}", word, arg(0))),
+            "notdir" => map_words(arg(0), &|word| word.rsplit('/').next().unwrap_or(word).to_string()),
+            "basename" => map_words(arg(0), &|word| match word.rfind('.') {
+                Some(dot) if !word[dot..].contains('/') => word[..dot].to_string(),
+                _ => word.to_string(),
+            }),
+            "filter" | "filter-out" => {
+                let patterns: Vec<&str> = arg(0).split_whitespace().collect();
+                arg(1)
+                    .split_whitespace()
+                    .filter(|word| {
+                        patterns.iter().any(|pattern| pattern_stem(pattern, word).is_some()) == (func == "filter")
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+            "sort" => {
+                let words: BTreeSet<&str> = arg(0).split_whitespace().collect();
+                words.into_iter().collect::<Vec<_>>().join(" ")
+            }
+            "strip" => arg(0).split_whitespace().collect::<Vec<_>>().join(" "),
+            "firstword" => arg(0).split_whitespace().next().unwrap_or("").to_string(),
+            _ => {
+                log::verbose(format!("Ignoring make function `{}`", func));
+                String::new()
+            }
+        }
+    }
+
+    /// Words of the expanded recipe if it runs the compiler, without the compiler
+    fn compiler_args(&self, recipe: &str) -> Option<Vec<String>> {
+        let cc = self.variable("CC", 0);
+        let words = split_words(&self.expand(recipe.trim_start_matches(['@', '-', '+', ' ']), 0));
+        let first = Path::new(words.first()?).file_name()?.to_string_lossy().to_string();
+        (first == cc || COMPILERS.contains(&first.as_str())).then(|| words[1..].to_vec())
+    }
+}
+
+/// Splits the arguments of a make function at commas outside of references
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut level = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' | '{' => level += 1,
+            ')' | '}' => level -= 1,
+            ',' if level == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+/// The part of the word that matched the % of the pattern
+fn pattern_stem<'w>(pattern: &str, word: &'w str) -> Option<&'w str> {
+    match pattern.split_once('%') {
+        Some((prefix, suffix)) => word
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix)),
+        None => (pattern == word).then_some(""),
+    }
+}
+
+fn patsubst(pattern: &str, replacement: &str, text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| match pattern_stem(pattern, word) {
+            Some(stem) => replacement.replacen('%', stem, 1),
+            None => word.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn import_make(root: &Path, file: &Path) -> anyhow::Result<ImportedBuild> {
+    let content = fs::read_to_string(file).context(format!("Failed to read {}", file.display()))?;
+    let make = Makefile::parse(root, &content);
+    let mut build = ImportedBuild::default();
+
+    if make.vars.contains_key("CC") {
+        build.compiler = Some(make.variable("CC", 0)).filter(|cc| !cc.is_empty() && !cc.contains(' '));
+    }
+    build.version = make.vars.get("VERSION").map(|_| make.variable("VERSION", 0)).filter(|v| !v.is_empty());
+
+    let mut vars: Vec<&String> = make.vars.keys().collect();
+    vars.sort();
+    // Test programs aren't part of the project's sources
+    vars.retain(|name| !name.contains("TEST"));
+
+    let mut words = Vec::new();
+    for name in &vars {
+        let value = split_words(&make.variable(name, 0));
+        if name.ends_with("FLAGS") || name.ends_with("LIBS") || MAKE_FLAG_VARS.contains(&name.as_str()) {
+            build.add_flags(&value, root);
+        }
+        words.extend(value);
+    }
+    for recipe in &make.recipes {
+        if let Some(args) = make.compiler_args(recipe) {
+            build.add_flags(&args, root);
+            words.extend(args);
+        }
+    }
+    for prerequisites in &make.prerequisites {
+        words.extend(split_words(&make.expand(prerequisites, 0)));
+    }
+
+    for word in &words {
+        let file = normalize(&root.join(word));
+        if file.extension().is_some_and(|ext| ext == "o") {
+            // Objects are usually built from a c file of the same name
+            for ext in util::SRC_EXTENSIONS {
+                build.add_source(file.with_extension(ext));
+            }
+        } else {
+            build.add_source(file);
+        }
+    }
+
+    let targets: Vec<String> = make
+        .targets
+        .iter()
+        .flat_map(|targets| split_words(&make.expand(targets, 0)))
+        .collect();
+    let archive = targets.iter().find(|target| target.ends_with(".a"));
+    let ar = make.variable("AR", 0);
+    let archives = archive.is_some()
+        || make.recipes.iter().any(|recipe| {
+            let recipe = make.expand(recipe.trim_start_matches(['@', '-', '+', ' ']), 0);
+            recipe.split_whitespace().next().is_some_and(|first| first == ar)
+        });
+    if archives {
+        build.proj_type = Some(ProjType::Lib);
+    }
+
+    build.name = match archive {
+        Some(archive) => {
+            let name = Path::new(archive).file_stem().unwrap().to_string_lossy().to_string();
+            Some(name.strip_prefix("lib").map(String::from).unwrap_or(name))
+        }
+        None => MAKE_NAME_VARS
+            .iter()
+            .filter(|name| make.vars.contains_key(**name))
+            .map(|name| make.variable(name, 0))
+            .find_map(|value| {
+                let first = value.split_whitespace().next()?.to_string();
+                Path::new(&first).file_name().map(|name| name.to_string_lossy().to_string())
+            }),
+    };
+    Ok(build)
+}
+
+/// Argument of a CMake command
+struct CMakeArg {
+    value: String,
+    quoted: bool,
+}
+
+/// Splits CMakeLists.txt into its commands and their arguments
+fn cmake_commands(content: &str) -> Vec<(String, Vec<CMakeArg>)> {
+    let mut commands = Vec::new();
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '#' {
+            for c in chars.by_ref() {
+                if c == '\n' {
+                    break;
+                }
+            }
+            continue;
+        }
+        if !(c.is_ascii_alphanumeric() || c == '_') {
+            continue;
+        }
+        let mut name = String::from(c);
+        while let Some(&c) = chars.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        while chars.peek().is_some_and(|c| *c == ' ' || *c == '\t') {
+            chars.next();
+        }
+        if chars.peek() != Some(&'(') {
+            continue;
+        }
+        chars.next();
+
+        let mut args = Vec::new();
+        let mut arg = String::new();
+        let mut level = 1;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let mut value = String::new();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' => value.extend(chars.next()),
+                            c => value.push(c),
+                        }
+                    }
+                    args.push(CMakeArg { value, quoted: true });
+                }
+                '#' => {
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                }
+                '(' => level += 1,
+                ')' => {
+                    level -= 1;
+                    if level == 0 {
+                        break;
+                    }
+                }
+                c if c.is_whitespace() => {}
+                c => {
+                    arg.push(c);
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                            break;
+                        }
+                        arg.push(c);
+                        chars.next();
+                    }
+                    args.push(CMakeArg {
+                        value: std::mem::take(&mut arg),
+                        quoted: false,
+                    });
+                }
+            }
+        }
+        commands.push((name.to_lowercase(), args));
+    }
+    commands
+}
+
+/// Keywords of CMake commands that are not sources, directories or libraries
+const CMAKE_KEYWORDS: [&str; 19] = [
+    "PUBLIC", "PRIVATE", "INTERFACE", "STATIC", "SHARED", "MODULE", "OBJECT", "WIN32",
+    "MACOSX_BUNDLE", "EXCLUDE_FROM_ALL", "SYSTEM", "BEFORE", "AFTER", "LINK_PUBLIC",
+    "LINK_PRIVATE", "debug", "optimized", "general", "CONFIGURE_DEPENDS",
+];
+
+#[derive(Default)]
+struct CMakeTarget {
+    name: String,
+    exe: bool,
+    sources: Vec<PathBuf>,
+    defines: Vec<String>,
+    include_dirs: Vec<PathBuf>,
+    flags: Vec<String>,
+    links: Vec<String>,
+    std: Option<String>,
+    extensions: Option<String>,
+}
+
+/// The state of a CMakeLists.txt after running its commands
+struct CMakeLists<'c> {
+    root: &'c Path,
+    vars: HashMap<String, Vec<String>>,
+    /// Settings that apply to every target
+    global: CMakeTarget,
+    targets: Vec<CMakeTarget>,
+}
+
+impl<'c> CMakeLists<'c> {
+    fn parse(root: &'c Path, content: &str) -> Self {
+        let mut cmake = Self {
+            root,
+            vars: HashMap::new(),
+            global: CMakeTarget::default(),
+            targets: Vec::new(),
+        };
+        let root_str = root.display().to_string();
+        for var in ["CMAKE_SOURCE_DIR", "CMAKE_CURRENT_SOURCE_DIR", "CMAKE_CURRENT_LIST_DIR", "PROJECT_SOURCE_DIR"] {
+            cmake.vars.insert(var.into(), vec![root_str.clone()]);
+        }
+
+        // Functions and macros only run when they are called
+        let mut skip_level = 0;
+        for (cmd, args) in cmake_commands(content) {
+            match cmd.as_str() {
+                "function" | "macro" => skip_level += 1,
+                "endfunction" | "endmacro" => skip_level -= 1,
+                _ if skip_level > 0 => {}
+                _ => {
+                    let args = cmake.expand_args(&args);
+                    cmake.run(&cmd, &args);
+                }
+            }
+        }
+        cmake
+    }
+
+    fn expand(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        // The innermost reference is expanded first, so ${${VAR}} works as well
+        for _ in 0..MAX_EXPANSION_DEPTH {
+            let Some(start) = text.rfind("${") else {
+                break;
+            };
+            let Some(len) = text[start..].find('}') else {
+                break;
+            };
+            let name = &text[start + 2..start + len];
+            let value = match self.vars.get(name) {
+                Some(value) => value.join(";"),
+                None => String::new(),
+            };
+            text.replace_range(start..start + len + 1, &value);
+        }
+        if let Some(start) = text.find("$ENV{") {
+            if let Some(len) = text[start..].find('}') {
+                let value = env::var(&text[start + 5..start + len]).unwrap_or_default();
+                text.replace_range(start..start + len + 1, &value);
+            }
+        }
+        text
+    }
+
+    /// Unquoted arguments are lists, so they are split at semicolons
+    fn expand_args(&self, args: &[CMakeArg]) -> Vec<String> {
+        let mut expanded = Vec::new();
+        for arg in args {
+            let value = self.expand(&arg.value);
+            if arg.quoted {
+                expanded.push(value);
+            } else {
+                expanded.extend(value.split(';').filter(|part| !part.is_empty()).map(String::from));
+            }
+        }
+        expanded
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        normalize(&self.root.join(path))
+    }
+
+    fn target(&mut self, name: &str) -> Option<&mut CMakeTarget> {
+        self.targets.iter_mut().find(|target| target.name == name)
+    }
+
+    fn run(&mut self, cmd: &str, args: &[String]) {
+        let values = |args: &[String]| -> Vec<String> {
+            args.iter()
+                .filter(|arg| !CMAKE_KEYWORDS.contains(&arg.as_str()))
+                .cloned()
+                .collect()
+        };
+        let Some((first, rest)) = args.split_first() else {
+            return;
+        };
+
+        match cmd {
+            "project" => {
+                self.vars.insert("PROJECT_NAME".into(), vec![first.clone()]);
+                if let Some(pos) = rest.iter().position(|arg| arg == "VERSION") {
+                    if let Some(version) = rest.get(pos + 1) {
+                        self.vars.insert("PROJECT_VERSION".into(), vec![version.clone()]);
+                    }
+                }
+            }
+            "set" => {
+                let end = rest
+                    .iter()
+                    .position(|arg| arg == "CACHE" || arg == "PARENT_SCOPE")
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    self.vars.remove(first);
+                } else {
+                    self.vars.insert(first.clone(), rest[..end].to_vec());
+                }
+            }
+            "unset" => {
+                self.vars.remove(first);
+            }
+            "list" if first == "APPEND" && !rest.is_empty() => {
+                self.vars.entry(rest[0].clone()).or_default().extend(rest[1..].iter().cloned());
+            }
+            "list" if first == "REMOVE_ITEM" && !rest.is_empty() => {
+                if let Some(list) = self.vars.get_mut(&rest[0]) {
+                    list.retain(|item| !rest[1..].contains(item));
+                }
+            }
+            "file" if (first == "GLOB" || first == "GLOB_RECURSE") && !rest.is_empty() => {
+                let root = self.root.display().to_string();
+                let mut files = Vec::new();
+                let mut args = rest[1..].iter();
+                while let Some(arg) = args.next() {
+                    match arg.as_str() {
+                        "LIST_DIRECTORIES" | "RELATIVE" => {
+                            args.next();
+                        }
+                        "CONFIGURE_DEPENDS" => {}
+                        pattern => {
+                            let pattern = pattern.strip_prefix(&root).unwrap_or(pattern).trim_start_matches('/');
+                            // GLOB_RECURSE searches the directories below the pattern's directory as well
+                            let pattern = match (first.as_str(), pattern.rsplit_once('/')) {
+                                ("GLOB_RECURSE", Some((dir, name))) => format!("{}/**/{}", dir, name),
+                                ("GLOB_RECURSE", None) => format!("**/{}", pattern),
+                                _ => pattern.to_string(),
+                            };
+                            files.extend(
+                                glob::find(self.root, &pattern)
+                                    .into_iter()
+                                    .map(|file| file.display().to_string()),
+                            );
+                        }
+                    }
+                }
+                files.sort();
+                self.vars.insert(rest[0].clone(), files);
+            }
+            "add_executable" | "add_library" => {
+                if rest.iter().any(|arg| arg == "IMPORTED" || arg == "ALIAS") {
+                    return;
+                }
+                let sources = values(rest).iter().map(|src| self.path(src)).collect();
+                self.targets.push(CMakeTarget {
+                    name: first.clone(),
+                    exe: cmd == "add_executable",
+                    sources,
+                    ..Default::default()
+                });
+            }
+            "target_sources" => {
+                let sources: Vec<PathBuf> = values(rest).iter().map(|src| self.path(src)).collect();
+                if let Some(target) = self.target(first) {
+                    target.sources.extend(sources);
+                }
+            }
+            "include_directories" => {
+                let dirs: Vec<PathBuf> = values(args).iter().map(|dir| self.path(dir)).collect();
+                self.global.include_dirs.extend(dirs);
+            }
+            "target_include_directories" => {
+                let dirs: Vec<PathBuf> = values(rest).iter().map(|dir| self.path(dir)).collect();
+                if let Some(target) = self.target(first) {
+                    target.include_dirs.extend(dirs);
+                }
+            }
+            "add_definitions" | "add_compile_options" => self.global.flags.extend(args.iter().cloned()),
+            "add_compile_definitions" => self.global.defines.extend(args.iter().cloned()),
+            "target_compile_definitions" => {
+                let defines = values(rest);
+                if let Some(target) = self.target(first) {
+                    target
+                        .defines
+                        .extend(defines.iter().map(|define| define.trim_start_matches("-D").to_string()));
+                }
+            }
+            "target_compile_options" => {
+                let flags = values(rest);
+                if let Some(target) = self.target(first) {
+                    target.flags.extend(flags);
+                }
+            }
+            "link_libraries" => self.global.links.extend(values(args)),
+            "target_link_libraries" => {
+                let links = values(rest);
+                if let Some(target) = self.target(first) {
+                    target.links.extend(links);
+                }
+            }
+            "target_compile_features" => {
+                let std = rest.iter().find_map(|feature| feature.strip_prefix("c_std_")).map(String::from);
+                if let Some(target) = self.target(first) {
+                    target.std = std.or(target.std.take());
+                }
+            }
+            "set_target_properties" => {
+                let Some(pos) = args.iter().position(|arg| arg == "PROPERTIES") else {
+                    return;
+                };
+                for name in &args[..pos] {
+                    for pair in args[pos + 1..].chunks(2) {
+                        if let (Some(target), [key, value]) = (self.target(name), pair) {
+                            match key.as_str() {
+                                "C_STANDARD" => target.std = Some(value.clone()),
+                                "C_EXTENSIONS" => target.extensions = Some(value.clone()),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            "add_subdirectory" => log::warn(format!(
+                "The subdirectory `{}` is not imported, only the top-level CMakeLists.txt is read",
+                first
+            )),
+            _ => log::debug(format!("Ignoring cmake command `{}`", cmd)),
+        }
+    }
+}
+
+/// CMake's standards are numbers, extensions are on unless disabled
+fn cmake_std(std: &str, extensions: Option<&str>) -> Option<Standard> {
+    let std = if std == "90" { "89" } else { std };
+    let gnu = !extensions.is_some_and(|ext| ["OFF", "FALSE", "NO", "0"].contains(&ext.to_uppercase().as_str()));
+    parse_std(&format!("{}{}", if gnu { "gnu" } else { "c" }, std))
+}
+
+fn import_cmake(root: &Path, file: &Path) -> anyhow::Result<ImportedBuild> {
+    let content = fs::read_to_string(file).context(format!("Failed to read {}", file.display()))?;
+    let cmake = CMakeLists::parse(root, &content);
+    let var = |name: &str| cmake.vars.get(name).and_then(|value| value.first()).cloned();
+
+    // The first executable is the project, or the first library if there is none
+    let main = cmake
+        .targets
+        .iter()
+        .position(|target| target.exe)
+        .or_else(|| cmake.targets.iter().position(|target| !target.sources.is_empty()))
+        .context(format!("Found no executable or library in {}", file.display()))?;
+
+    // Libraries of the same file that the project links are compiled into it
+    let mut included = vec![main];
+    let mut i = 0;
+    while i < included.len() {
+        for link in &cmake.targets[included[i]].links {
+            if let Some(pos) = cmake.targets.iter().position(|target| target.name == *link) {
+                if !included.contains(&pos) {
+                    included.push(pos);
+                }
+            }
+        }
+        i += 1;
+    }
+
+    let mut build = ImportedBuild {
+        name: var("PROJECT_NAME").or_else(|| Some(cmake.targets[main].name.clone())),
+        version: var("PROJECT_VERSION"),
+        compiler: var("CMAKE_C_COMPILER"),
+        proj_type: Some(match cmake.targets[main].exe {
+            true => ProjType::Bin,
+            false => ProjType::Lib,
+        }),
+        ..Default::default()
+    };
+
+    let target_names: Vec<&str> = cmake.targets.iter().map(|target| target.name.as_str()).collect();
+    for target in included.iter().map(|i| &cmake.targets[*i]).chain([&cmake.global]) {
+        for src in &target.sources {
+            build.add_source(src.clone());
+        }
+        for define in &target.defines {
+            push_unique(&mut build.defines, define.clone());
+        }
+        for dir in &target.include_dirs {
+            push_unique(&mut build.include_dirs, dir.clone());
+        }
+        build.add_flags(&target.flags, root);
+        for link in &target.links {
+            if target_names.contains(&link.as_str()) {
+                continue;
+            }
+            match link.as_str() {
+                "Threads::Threads" => build.add_library("pthread"),
+                link if link.contains("::") || link.contains("$<") || link.contains('/') => {
+                    build.ignored.insert(link.to_string());
+                }
+                link => build.add_library(link),
+            }
+        }
+    }
+
+    let main_target = &cmake.targets[main];
+    if build.std.is_none() {
+        let std = main_target.std.clone().or_else(|| var("CMAKE_C_STANDARD"));
+        let extensions = main_target.extensions.clone().or_else(|| var("CMAKE_C_EXTENSIONS"));
+        build.std = std.and_then(|std| cmake_std(&std, extensions.as_deref()));
+    }
+
+    // Other executables become binaries if they only add their own main file
+    for target in cmake.targets.iter().filter(|target| target.exe && target.name != main_target.name) {
+        let own: Vec<&PathBuf> = target
+            .sources
+            .iter()
+            .filter(|src| util::is_src_file(src) && !build.sources.contains(*src))
+            .collect();
+        match own.as_slice() {
+            [file] => {
+                build.binaries.insert(target.name.clone(), file.to_path_buf());
+            }
+            _ => log::warn(format!(
+                "The executable `{}` is not imported, binaries can only have one file of their own",
+                target.name
+            )),
+        }
+    }
+    Ok(build)
+}
+
+/// Generates a project.lua from the project's existing Make or CMake build
+pub fn import_proj(proj: &Project, format: ImportFormat) -> anyhow::Result<()> {
+    let root = proj.root_dir.as_path();
+    let cfg_file = root.join(CONFIG_FILE);
+    if cfg_file.exists() {
+        bail!("{} already exists, remove it to import the build", cfg_file.display());
+    }
+
+    let build_file = format.build_file(root);
+    let db_file = find_compile_db(root);
+    if build_file.is_none() && db_file.is_none() {
+        bail!(
+            "Found neither a {} nor a {} in {}",
+            format.file_name(),
+            COMPILE_DB_FILE,
+            root.display()
+        );
+    }
+
+    let mut build = match &build_file {
+        Some(file) => {
+            log::status("Importing", file.display());
+            match format {
+                ImportFormat::Make => import_make(root, file)?,
+                ImportFormat::CMake => import_cmake(root, file)?,
+            }
+        }
+        None => ImportedBuild::default(),
+    };
+    if let Some(db_file) = &db_file {
+        log::status("Importing", db_file.display());
+        build.merge_compile_db(import_compile_db(root, db_file)?);
+    }
+    build.split_binaries();
+
+    if build.sources.is_empty() {
+        log::warn("Found no source files in the build, every file in src will be compiled");
+    }
+    if !build.ignored.is_empty() {
+        log::warn(format!(
+            "Some flags have no equivalent in {} and were left out: {}",
+            CONFIG_FILE,
+            build.ignored.iter().cloned().collect::<Vec<_>>().join(" ")
+        ));
+    }
+
+    let origin = build_file
+        .iter()
+        .chain(db_file.iter())
+        .filter_map(|file| glob::relative(file, root))
+        .collect::<Vec<_>>()
+        .join(" and ");
+    let content = build.layout(root, &proj.name, &origin);
+
+    log::status("Creating", cfg_file.display());
+    if !log::is_dry_run() {
+        fs::write(&cfg_file, content).context(format!("Failed to write {}", cfg_file.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        split_words(line)
+    }
+
+    /// Project directory with the files, removed again by `cleanup`
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("surtur-import-{}-{}", name, std::process::id()));
+        for (file, content) in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        root
+    }
+
+    fn cleanup(root: &Path) {
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn flags_with_separate_values() {
+        let mut build = ImportedBuild::default();
+        let base = Path::new("/proj/build");
+        build.add_flags(&args("-I ../include -Isrc -isystem /opt/inc -D DEBUG -DLEVEL=2 -l m -lz -pthread"), base);
+
+        assert_eq!(build.include_dirs, [PathBuf::from("/proj/include"), "/proj/build/src".into(), "/opt/inc".into()]);
+        assert_eq!(build.defines, ["DEBUG", "LEVEL=2"]);
+        assert_eq!(build.libraries, ["m", "z", "pthread"]);
+        assert!(build.ignored.is_empty());
+    }
+
+    #[test]
+    fn standards_and_their_aliases() {
+        let mut build = ImportedBuild::default();
+        build.add_flags(&args("-std=gnu11"), Path::new("/"));
+        assert_eq!(build.std, Some(Standard::Gnu11));
+        build.add_flags(&args("-std=iso9899:2018"), Path::new("/"));
+        assert_eq!(build.std, Some(Standard::C17));
+        build.add_flags(&args("-std=c++17"), Path::new("/"));
+        assert_eq!(build.std, Some(Standard::C17));
+        assert_eq!(build.ignored, BTreeSet::from(["-std=c++17".to_string()]));
+    }
+
+    #[test]
+    fn ignored_and_dropped_flags() {
+        let mut build = ImportedBuild::default();
+        build.add_flags(
+            &args("-c -O2 -g -Wall -MD -MF deps.d -o main.o -L /usr/lib -include config.h -U NDEBUG -UTRACE -fPIC"),
+            Path::new("/"),
+        );
+
+        let ignored: Vec<&str> = build.ignored.iter().map(String::as_str).collect();
+        assert_eq!(ignored, ["-L /usr/lib", "-UNDEBUG", "-UTRACE", "-fPIC", "-include config.h"]);
+        assert!(build.defines.is_empty() && build.include_dirs.is_empty() && build.libraries.is_empty());
+    }
+
+    #[test]
+    fn compile_db_replaces_sources_and_flags_of_the_build_file() {
+        let mut build = ImportedBuild {
+            compiler: Some("clang".into()),
+            std: Some(Standard::C99),
+            sources: BTreeSet::from(["/proj/src/old.c".into()]),
+            defines: vec!["OLD".into()],
+            include_dirs: vec!["/proj/old".into()],
+            libraries: vec!["m".into()],
+            ignored: BTreeSet::from(["-fPIC".into()]),
+            ..Default::default()
+        };
+        build.merge_compile_db(ImportedBuild {
+            compiler: Some("gcc".into()),
+            std: Some(Standard::C11),
+            sources: BTreeSet::from(["/proj/src/main.c".into()]),
+            defines: vec!["NEW".into()],
+            include_dirs: vec!["/proj/include".into()],
+            libraries: vec!["m".into(), "dl".into()],
+            ignored: BTreeSet::from(["-fno-common".into()]),
+            ..Default::default()
+        });
+
+        assert_eq!(build.compiler.as_deref(), Some("clang"));
+        assert_eq!(build.std, Some(Standard::C11));
+        assert_eq!(build.sources, BTreeSet::from(["/proj/src/main.c".into()]));
+        assert_eq!(build.defines, ["NEW"]);
+        assert_eq!(build.include_dirs, [PathBuf::from("/proj/include")]);
+        assert_eq!(build.libraries, ["m", "dl"]);
+        assert_eq!(build.ignored.len(), 2);
+    }
+
+    #[test]
+    fn empty_compile_db_keeps_the_build_file() {
+        let mut build = ImportedBuild {
+            sources: BTreeSet::from(["/proj/src/main.c".into()]),
+            defines: vec!["KEEP".into()],
+            ..Default::default()
+        };
+        build.merge_compile_db(ImportedBuild {
+            std: Some(Standard::Gnu99),
+            ..Default::default()
+        });
+
+        assert_eq!(build.sources.len(), 1);
+        assert_eq!(build.defines, ["KEEP"]);
+        assert_eq!(build.std, Some(Standard::Gnu99));
+    }
+
+    #[test]
+    fn layout_of_a_binary_project() {
+        let root = project(
+            "bin",
+            &[
+                ("src/main.c", "int main(void) { return 0; }\n"),
+                ("src/util.c", "int util(void) { return 1; }\n"),
+                ("src/tool.c", "int main(void) { return 1; }\n"),
+                ("src/unused.c", "int unused;\n"),
+            ],
+        );
+        let mut build = ImportedBuild {
+            version: Some("1.2".into()),
+            defines: vec!["NAME=\"app\"".into()],
+            include_dirs: vec![root.join("include")],
+            libraries: vec!["m".into()],
+            ..Default::default()
+        };
+        for file in ["main.c", "util.c", "tool.c"] {
+            build.add_source(root.join("src").join(file));
+        }
+        build.split_binaries();
+        let content = build.layout(&root, "app", "Makefile");
+        cleanup(&root);
+
+        assert_eq!(
+            content,
+            concat!(
+                "-- imported from Makefile by `surtur init`\n",
+                "-- properties\n",
+                "Name = \"app\"\n",
+                "Props = {\n",
+                "    std = \"gnu17\",\n",
+                "    version = \"1.2\",\n",
+                "    type = \"bin\",\n",
+                "    compiler = \"gcc\",\n",
+                "}\n",
+                "\n-- source files that are not part of the build\nExclude = {\n    \"unused.c\",\n}\n",
+                "\n-- preprocessor definitions\nDefines = {\n    \"NAME=\\\"app\\\"\",\n}\n",
+                "\n-- directories searched for headers\nIncludeDirs = {\n    \"include\",\n}\n",
+                "\n-- linked system libraries\nLibraries = {\n    \"m\",\n}\n",
+                "\n-- additional executables\nBinaries = {\n    [\"tool\"] = \"src/tool.c\",\n}\n",
+                "\n-- external dependencies\nDependencies = {\n}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn layout_of_a_library_with_sources_outside_of_src() {
+        let root = project(
+            "lib",
+            &[
+                ("lib/list.c", "int list_len(void) { return 0; }\n"),
+                ("lib/demo.c", "int main(void) { return 0; }\n"),
+            ],
+        );
+        let mut build = ImportedBuild {
+            name: Some("list".into()),
+            std: Some(Standard::C99),
+            ..Default::default()
+        };
+        build.add_source(root.join("lib/list.c"));
+        build.add_source(root.join("lib/demo.c"));
+        build.add_source(root.join("lib/missing.c"));
+        build.proj_type = Some(ProjType::Lib);
+        build.split_binaries();
+        let content = build.layout(&root, "dir", "CMakeLists.txt");
+        cleanup(&root);
+
+        assert!(content.contains("Name = \"list\"\n"));
+        assert!(content.contains("    std = \"c99\",\n    version = \"0.1\",\n    type = \"lib\",\n"));
+        assert!(content.contains("\n-- source files of the build\nSources = {\n    \"lib/list.c\",\n}\n"));
+        assert!(!content.contains("Binaries"));
+        assert!(!content.contains("Defines"));
+    }
+}
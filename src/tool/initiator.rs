@@ -1,16 +1,23 @@
-/// Handling of initialization and
-/// fixing of projects. This will
-/// add missing config files or
-/// fix issues [WIP]
-/// 
-/// Later on, it will also be able
-/// to migrate Make projects
+//! Handling of initialization and
+//! fixing of projects. This will
+//! add missing config files or
+//! fix issues [WIP]
+//!
+//! Projects built with Make or CMake
+//! are migrated by the importer
 
 use std::fs;
 
-use super::creator::Project;
+use super::{
+    creator::Project,
+    importer::{self, ImportFormat},
+};
+
+pub fn init_proj(proj: &Project, from: Option<ImportFormat>) -> anyhow::Result<()> {
+    if let Some(format) = from {
+        return importer::import_proj(proj, format);
+    }
 
-pub fn init_proj(proj: &Project) -> anyhow::Result<()> {
     let cfg_file = proj.root_dir.join("project.lua");
     let main_file = proj.root_dir.join("src").join("main.c");
     if fs::metadata(cfg_file).is_err() {
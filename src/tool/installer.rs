@@ -0,0 +1,263 @@
+//! Handling of installing libraries into a standard
+//! layout for projects that don't use surtur. Every
+//! installed file is recorded in a manifest, so the
+//! library can be uninstalled again.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{self, Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use mlua::{Lua, Table};
+
+use crate::util::{self, glob, log, lua_utils::lua_string};
+
+use super::{
+    config::{Config, ProjType},
+    executor::{self, Target},
+};
+
+pub const DEFAULT_PREFIX: &str = "/usr/local";
+
+/// Manifest of the installed files, relative to the prefix
+fn manifest_path(prefix: &Path, name: &str) -> PathBuf {
+    prefix.join("share").join("surtur").join(format!("{}.lua", name))
+}
+
+/// Files that were installed so far, they are removed again if the installation
+/// fails before its manifest is saved. Files they replaced are put back then
+struct Installation<'i> {
+    prefix: &'i Path,
+    files: Vec<PathBuf>,
+    /// Files that were there before, with the path they are kept at meanwhile
+    backups: Vec<(PathBuf, PathBuf)>,
+    finished: bool,
+}
+
+impl Drop for Installation<'_> {
+    fn drop(&mut self) {
+        if log::is_dry_run() {
+            return;
+        }
+        if self.finished {
+            for (_, backup) in &self.backups {
+                let _ = fs::remove_file(backup);
+            }
+            return;
+        }
+        for file in self.files.iter().rev() {
+            log::status("Removing", file.display());
+            let _ = fs::remove_file(file);
+        }
+        for (file, backup) in &self.backups {
+            log::status("Restoring", file.display());
+            let _ = fs::rename(backup, file);
+        }
+    }
+}
+
+impl Installation<'_> {
+    /// Creates the directory of the file and moves a file that is already there out of the way
+    fn prepare(&mut self, to: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+        }
+        if to.exists() {
+            let name = to.file_name().unwrap_or_default().to_string_lossy();
+            let backup = to.with_file_name(format!(".{}.surtur-old", name));
+            fs::rename(to, &backup).context(format!("Failed to move {} out of the way", to.display()))?;
+            self.backups.push((to.to_path_buf(), backup));
+        }
+        Ok(())
+    }
+
+    fn copy(&mut self, from: &Path, to: PathBuf) -> anyhow::Result<()> {
+        log::status("Installing", to.display());
+        if !log::is_dry_run() {
+            self.prepare(&to)?;
+            fs::copy(from, &to)
+                .context(format!("Failed to copy {} to {}", from.display(), to.display()))?;
+        }
+        self.files.push(to);
+        Ok(())
+    }
+
+    fn write(&mut self, to: PathBuf, content: &str) -> anyhow::Result<()> {
+        log::status("Installing", to.display());
+        if !log::is_dry_run() {
+            self.prepare(&to)?;
+            fs::write(&to, content).context(format!("Failed to write {}", to.display()))?;
+        }
+        self.files.push(to);
+        Ok(())
+    }
+
+    fn save_manifest(mut self, name: &str) -> anyhow::Result<()> {
+        let mut content = String::from("-- Files installed by surtur, used by `surtur install --uninstall`\nFiles = {\n");
+        for file in &self.files {
+            let file = glob::relative(file, self.prefix).unwrap_or_else(|| file.display().to_string());
+            let _ = writeln!(content, "    {},", lua_string(&file));
+        }
+        content.push_str("}\n");
+        let path = manifest_path(self.prefix, name);
+        self.write(path, &content)?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+/// Generates the pkg-config file of the library, `dep_libs` are
+/// the system libraries the library's dependencies link against
+fn pkg_config(prefix: &Path, cfg: &Config, dep_names: &[String], dep_libs: &[&String]) -> String {
+    let mut libs = format!("-L${{libdir}} -l{}", cfg.name);
+    // Static libraries don't carry the libraries they need, so their users have to link them
+    if !dep_names.is_empty() {
+        let _ = write!(libs, " -L${{libdir}}/{}", cfg.name);
+    }
+    for lib in dep_names.iter().chain(cfg.libraries.iter()) {
+        let _ = write!(libs, " -l{}", lib);
+    }
+    let mut pc = format!(
+        concat!(
+            "prefix={}\n",
+            "libdir=${{prefix}}/lib\n",
+            "includedir=${{prefix}}/include\n",
+            "\n",
+            "Name: {}\n",
+            "Description: The {} library\n",
+            "Version: {}\n",
+            "Cflags: -I${{includedir}}/{}\n",
+            "Libs: {}\n",
+        ),
+        prefix.display(),
+        cfg.name,
+        cfg.name,
+        cfg.props.proj_version,
+        cfg.name,
+        libs
+    );
+    if !dep_libs.is_empty() {
+        let libs: Vec<String> = dep_libs.iter().map(|lib| format!("-l{}", lib)).collect();
+        let _ = writeln!(pc, "Libs.private: {}", libs.join(" "));
+    }
+    pc
+}
+
+/// Builds the library in release mode and installs its archive, headers,
+/// dependencies and a pkg-config file into the prefix
+pub fn install_proj(proj_dir: &PathBuf, mut cfg: Config, prefix: &Path) -> anyhow::Result<()> {
+    if cfg.props.proj_type != ProjType::Lib {
+        bail!("Only library projects can be installed, `{}` is a binary project", cfg.name);
+    }
+    // The prefix ends up in the pkg-config file, so it has to be absolute
+    let prefix = path::absolute(prefix)?;
+    let name = cfg.name.clone();
+    let root_name = util::root_dir_name(proj_dir)
+        .context("Failed to get root name of project")?
+        .to_string();
+
+    // Transitive dependencies are installed as well, packages go
+    // before the packages they depend on, like they are linked
+    let packages = cfg.deps.resolve(&cfg.features, false)?;
+    let dep_names: Vec<String> = packages.iter().rev().map(|pkg| pkg.name.clone()).collect();
+    let mut dep_libs: Vec<&String> = packages
+        .iter()
+        .flat_map(|pkg| &pkg.cfg.libraries)
+        .filter(|lib| !cfg.libraries.contains(*lib))
+        .collect();
+    dep_libs.sort();
+    dep_libs.dedup();
+    let include_dir = proj_dir.join("include");
+    let pc = pkg_config(&prefix, &cfg, &dep_names, &dep_libs);
+
+    executor::build_proj(proj_dir, &mut cfg, Target::Main, false, false, true)?;
+
+    let mut installation = Installation {
+        prefix: &prefix,
+        files: Vec::new(),
+        backups: Vec::new(),
+        finished: false,
+    };
+    let lib_dir = prefix.join("lib");
+    let out_dir = executor::profile_dir(true);
+
+    installation.copy(
+        &out_dir.join(format!("{}.a", root_name)),
+        lib_dir.join(format!("lib{}.a", name)),
+    )?;
+    // Shared libraries are not built by surtur, but can come from build scripts
+    let shared = out_dir.join(format!("{}.so", root_name));
+    if shared.exists() {
+        installation.copy(&shared, lib_dir.join(format!("lib{}.so", name)))?;
+    }
+    // Dependencies go into a directory of the library, so they
+    // can't replace libraries of the system or of other packages
+    for dep in &dep_names {
+        installation.copy(
+            &util::target_dir().join(dep).join(format!("{}.a", dep)),
+            lib_dir.join(&name).join(format!("lib{}.a", dep)),
+        )?;
+    }
+
+    if include_dir.exists() {
+        let mut headers: Vec<PathBuf> = util::get_header_files(&include_dir).into_iter().collect();
+        headers.sort();
+        for header in headers {
+            let rel = header.strip_prefix(&include_dir)?;
+            installation.copy(&header, prefix.join("include").join(&name).join(rel))?;
+        }
+    }
+
+    installation.write(lib_dir.join("pkgconfig").join(format!("{}.pc", name)), &pc)?;
+    installation.save_manifest(&name)?;
+
+    log::status("Installed", format!("{} to {}", name, prefix.display()));
+    Ok(())
+}
+
+/// Removes every file recorded in the library's manifest
+pub fn uninstall_proj(name: &str, prefix: &Path) -> anyhow::Result<()> {
+    let prefix = path::absolute(prefix)?;
+    let path = manifest_path(&prefix, name);
+    if !path.exists() {
+        bail!("`{}` is not installed in {}, found no {}", name, prefix.display(), path.display());
+    }
+
+    let lua = Lua::new();
+    lua.load(fs::read_to_string(&path)?)
+        .exec()
+        .context(format!("Failed to load install manifest {}", path.display()))?;
+    let files: Table = lua
+        .globals()
+        .get("Files")
+        .context("Install manifest requires a `Files` table")?;
+
+    let mut files = files
+        .sequence_values::<String>()
+        .map(|file| Ok(prefix.join(file?)))
+        .collect::<anyhow::Result<Vec<PathBuf>>>()?;
+    files.push(path);
+
+    for file in &files {
+        if !file.exists() {
+            continue;
+        }
+        log::status("Removing", file.display());
+        if !log::is_dry_run() {
+            fs::remove_file(file).context(format!("Failed to remove {}", file.display()))?;
+        }
+    }
+    // Directories that only contained the library, like include/<name>
+    for file in &files {
+        for dir in file.ancestors().skip(1).take_while(|dir| *dir != prefix) {
+            if log::is_dry_run() || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+
+    log::status("Uninstalled", format!("{} from {}", name, prefix.display()));
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! The lockfile (surtur.lock) pins the version and commit of every
+//! git dependency, so everyone who builds the project builds the same
+//! code. Builds only read it, `surtur update` is the only command that writes it.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+use mlua::{Lua, Table};
+
+use crate::util::lua_utils::lua_string;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LockedPackage {
+    pub name: String,
+    pub source: String,
+    /// Version requirement, tag, commit or branch the project.lua asks for
+    pub version: String,
+    /// Empty if the package isn't checked out at a tag
+    pub tag: String,
+    pub commit: String,
+    /// Checksum of the files of the checked out commit
+    pub checksum: String,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Lockfile {
+    pub packages: Vec<LockedPackage>,
+}
+
+impl LockedPackage {
+    /// The tag, or the commit if the package isn't checked out at a tag
+    pub fn revision(&self) -> &str {
+        if self.tag.is_empty() {
+            self.commit.get(..12).unwrap_or(&self.commit)
+        } else {
+            &self.tag
+        }
+    }
+}
+
+impl Lockfile {
+    /// Loads the lockfile, a missing lockfile doesn't lock anything
+    pub fn load(path: &Path) -> anyhow::Result<Lockfile> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        let lua = Lua::new();
+        lua.load(fs::read_to_string(path)?)
+            .exec()
+            .context(format!("Failed to load lockfile {}", path.display()))?;
+        let table: Table = lua
+            .globals()
+            .get("Packages")
+            .context("Lockfile requires a `Packages` table")?;
+        let mut packages = Vec::new();
+        for entry in table.sequence_values::<Table>() {
+            let entry = entry.context("Failed to get locked package")?;
+            packages.push(LockedPackage {
+                name: entry.get("name")?,
+                source: entry.get("source")?,
+                version: entry.get("version")?,
+                tag: entry.get("tag")?,
+                commit: entry.get("commit")?,
+                checksum: entry.get("checksum")?,
+            });
+        }
+        Ok(Lockfile { packages })
+    }
+
+    pub fn find(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|pkg| pkg.name == name)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut packages: Vec<&LockedPackage> = self.packages.iter().collect();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut content = String::from(
+            "-- Versions of all git dependencies, generated by `surtur update`. Don't edit it by hand\nPackages = {\n",
+        );
+        for pkg in packages {
+            content.push_str(&format!(
+                "    {{\n        name = {},\n        source = {},\n        version = {},\n        tag = {},\n        commit = {},\n        checksum = {},\n    }},\n",
+                lua_string(&pkg.name),
+                lua_string(&pkg.source),
+                lua_string(&pkg.version),
+                lua_string(&pkg.tag),
+                lua_string(&pkg.commit),
+                lua_string(&pkg.checksum)
+            ));
+        }
+        content.push_str("}\n");
+        fs::write(path, content).context(format!("Failed to save lockfile to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn package(name: &str, tag: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.into(),
+            source: format!("https://github.com/Surtur-Team/{}", name),
+            version: "^1.0".into(),
+            tag: tag.into(),
+            commit: "0123456789abcdef0123456789abcdef01234567".into(),
+            checksum: "e3b0c44298fc1c149afbf4c8996fb924".into(),
+        }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("surtur-{}-{}.lock", name, std::process::id()))
+    }
+
+    #[test]
+    fn saved_lockfile_loads_sorted_by_name() {
+        let path = temp_file("roundtrip");
+        let lockfile = Lockfile {
+            packages: vec![package("zlib", "v1.3.0"), package("args", "")],
+        };
+        lockfile.save(&path).unwrap();
+        let loaded = Lockfile::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.packages, vec![package("args", ""), package("zlib", "v1.3.0")]);
+        assert_eq!(loaded.find("zlib").unwrap().revision(), "v1.3.0");
+        assert_eq!(loaded.find("args").unwrap().revision(), "0123456789ab");
+        assert!(loaded.find("missing").is_none());
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let path = temp_file("escape");
+        let mut pkg = package("quoted", "");
+        pkg.version = "branch \"main\"\n\\".into();
+        let lockfile = Lockfile {
+            packages: vec![pkg.clone()],
+        };
+        lockfile.save(&path).unwrap();
+        let loaded = Lockfile::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.packages, vec![pkg]);
+    }
+
+    #[test]
+    fn missing_lockfile_is_empty() {
+        let loaded = Lockfile::load(&temp_file("missing")).unwrap();
+        assert!(loaded.packages.is_empty());
+    }
+}
@@ -1,7 +1,22 @@
+pub mod analyzer;
+pub mod bench;
+pub mod bundler;
+pub mod cleaner;
 pub mod compiler;
 pub mod config;
 pub mod creator;
 pub mod deps;
+pub mod documenter;
+pub mod editor;
 pub mod executor;
+pub mod exporter;
+pub mod formatter;
+pub mod graph;
+pub mod importer;
 pub mod initiator;
-pub mod scripts;
\ No newline at end of file
+pub mod installer;
+pub mod lockfile;
+pub mod scripts;
+pub mod semver;
+pub mod workspace;
+pub mod watcher;
@@ -1,7 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use mlua::Lua;
 
+use crate::util::log;
+
 pub struct ScriptManager {
     pre_scripts: Vec<PathBuf>,
     post_scripts: Vec<PathBuf>,
@@ -17,17 +19,30 @@ impl ScriptManager {
         }
     }
 
+    /// All pre and post build scripts
+    pub fn scripts(&self) -> impl Iterator<Item = &PathBuf> {
+        self.pre_scripts.iter().chain(self.post_scripts.iter())
+    }
+
     pub fn pre_exec(&self) -> mlua::Result<()> {
         for script in &self.pre_scripts {
-            self.lua_ctx.load(script.as_path()).exec()?;
+            self.exec_script(script)?;
         }
         Ok(())
     }
 
     pub fn post_exec(&self) -> mlua::Result<()> {
         for script in &self.post_scripts {
-            self.lua_ctx.load(script.as_path()).exec()?;
+            self.exec_script(script)?;
         }
         Ok(())
     }
+
+    fn exec_script(&self, script: &Path) -> mlua::Result<()> {
+        log::status("Running", format!("script {}", script.display()));
+        if log::is_dry_run() {
+            return Ok(());
+        }
+        self.lua_ctx.load(script).exec()
+    }
 }
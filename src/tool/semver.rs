@@ -0,0 +1,239 @@
+//! Semantic versions of dependencies and the requirements
+//! they are matched against, following the rules of cargo:
+//! `^1.2` (or just `1.2`) allows `>=1.2.0, <2.0.0`, `~1.2.3` allows
+//! `>=1.2.3, <1.3.0` and `=2.0.0` only allows `2.0.0`
+
+use std::{cmp::Ordering, fmt::Display};
+
+use anyhow::{bail, Context};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Pre-release part after the `-`, empty for releases
+    pub pre: String,
+}
+
+impl Version {
+    /// Parses versions like `1.2.3`, `1.2.3-rc1` and git tags like `v1.2.3`
+    pub fn parse(version: &str) -> anyhow::Result<Version> {
+        let version = version.trim();
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let (numbers, pre) = match version.split_once('-') {
+            Some((numbers, pre)) => (numbers, pre.to_string()),
+            None => (version, String::new()),
+        };
+        let parts = parse_numbers(numbers)?;
+        if parts.len() != 3 {
+            bail!("Invalid version `{}`, expected major.minor.patch", version);
+        }
+        Ok(Version {
+            major: parts[0],
+            minor: parts[1],
+            patch: parts[2],
+            pre,
+        })
+    }
+
+    fn new(major: u64, minor: u64, patch: u64) -> Version {
+        Version {
+            major,
+            minor,
+            patch,
+            pre: String::new(),
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            // Pre-releases come before the release
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Op {
+    Caret,
+    Tilde,
+    Exact,
+    Any,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VersionReq {
+    op: Op,
+    /// Missing minor and patch versions are zero
+    min: Version,
+    /// Number of version parts that were given
+    parts: usize,
+}
+
+impl VersionReq {
+    pub fn parse(req: &str) -> anyhow::Result<VersionReq> {
+        let req = req.trim();
+        let (op, version) = if let Some(version) = req.strip_prefix('^') {
+            (Op::Caret, version)
+        } else if let Some(version) = req.strip_prefix('~') {
+            (Op::Tilde, version)
+        } else if let Some(version) = req.strip_prefix('=') {
+            (Op::Exact, version)
+        } else if req == "*" {
+            return Ok(VersionReq {
+                op: Op::Any,
+                min: Version::new(0, 0, 0),
+                parts: 0,
+            });
+        } else {
+            (Op::Caret, req)
+        };
+
+        let (numbers, pre) = match version.trim().split_once('-') {
+            Some((numbers, pre)) => (numbers, pre.to_string()),
+            None => (version.trim(), String::new()),
+        };
+        let parts = parse_numbers(numbers).context(format!("Invalid version requirement `{}`", req))?;
+        if parts.len() > 3 {
+            bail!("Invalid version requirement `{}`", req);
+        }
+        if !pre.is_empty() && (op != Op::Exact || parts.len() != 3) {
+            bail!("Pre-release versions can only be required exactly, like `=1.0.0-rc1`");
+        }
+        Ok(VersionReq {
+            op,
+            min: Version {
+                major: parts[0],
+                minor: parts.get(1).copied().unwrap_or(0),
+                patch: parts.get(2).copied().unwrap_or(0),
+                pre,
+            },
+            parts: parts.len(),
+        })
+    }
+
+    /// First version that is too high, `None` if there is no upper bound
+    fn max(&self) -> Option<Version> {
+        let Version { major, minor, patch, .. } = self.min;
+        match (self.op, self.parts) {
+            (Op::Any, _) => None,
+            (Op::Exact, 3) => Some(Version::new(major, minor, patch + 1)),
+            (Op::Exact | Op::Tilde | Op::Caret, 1) => Some(Version::new(major + 1, 0, 0)),
+            (Op::Exact | Op::Tilde, _) => Some(Version::new(major, minor + 1, 0)),
+            // Caret allows every change that leaves the first non-zero part alone
+            (Op::Caret, _) if major > 0 => Some(Version::new(major + 1, 0, 0)),
+            (Op::Caret, 2) => Some(Version::new(0, minor + 1, 0)),
+            (Op::Caret, _) if minor > 0 => Some(Version::new(0, minor + 1, 0)),
+            (Op::Caret, _) => Some(Version::new(0, 0, patch + 1)),
+        }
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        if self.op == Op::Exact && self.parts == 3 {
+            return *version == self.min;
+        }
+        // Pre-releases have to be asked for explicitly
+        if !version.pre.is_empty() {
+            return false;
+        }
+        *version >= self.min && self.max().is_none_or(|max| *version < max)
+    }
+}
+
+fn parse_numbers(numbers: &str) -> anyhow::Result<Vec<u64>> {
+    numbers
+        .split('.')
+        .map(|part| {
+            part.parse::<u64>()
+                .context(format!("Invalid version number `{}` in `{}`", part, numbers))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(req: &str, version: &str) -> bool {
+        VersionReq::parse(req).unwrap().matches(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn caret_of_zero_versions_only_allows_patches_of_the_first_non_zero_part() {
+        assert!(matches("^0.0.3", "0.0.3"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+        assert!(!matches("^0.0.3", "0.1.0"));
+        assert!(matches("^0.2", "0.2.9"));
+        assert!(!matches("^0.2", "0.3.0"));
+        assert!(matches("1.2", "1.9.0"));
+        assert!(!matches("1.2", "2.0.0"));
+    }
+
+    #[test]
+    fn tilde_with_major_only_allows_minor_updates() {
+        assert!(matches("~1", "1.0.0"));
+        assert!(matches("~1", "1.9.3"));
+        assert!(!matches("~1", "2.0.0"));
+        assert!(matches("~1.2.3", "1.2.9"));
+        assert!(!matches("~1.2.3", "1.3.0"));
+    }
+
+    #[test]
+    fn exact_without_patch_allows_every_patch() {
+        assert!(matches("=1.2", "1.2.0"));
+        assert!(matches("=1.2", "1.2.7"));
+        assert!(!matches("=1.2", "1.3.0"));
+        assert!(matches("=1.2.3", "1.2.3"));
+        assert!(!matches("=1.2.3", "1.2.4"));
+    }
+
+    #[test]
+    fn pre_releases_have_to_be_required_exactly() {
+        assert!(!matches("^1.0", "1.1.0-rc1"));
+        assert!(!matches("*", "1.0.0-rc1"));
+        assert!(matches("=1.0.0-rc1", "1.0.0-rc1"));
+        assert!(!matches("=1.0.0", "1.0.0-rc1"));
+        assert!(VersionReq::parse("^1.0.0-rc1").is_err());
+    }
+
+    #[test]
+    fn versions_are_compared_by_number() {
+        let parse = |version| Version::parse(version).unwrap();
+        assert!(parse("1.10.0") > parse("1.9.0"));
+        assert!(parse("v2.0.0") > parse("1.99.99"));
+        assert!(parse("1.0.0-rc1") < parse("1.0.0"));
+        assert!(matches("^1.9", "1.10.0"));
+    }
+
+    #[test]
+    fn invalid_versions_are_rejected() {
+        assert!(Version::parse("1.2").is_err());
+        assert!(Version::parse("1.x.0").is_err());
+        assert!(VersionReq::parse("^1.2.3.4").is_err());
+        assert!(VersionReq::parse("latest").is_err());
+    }
+}
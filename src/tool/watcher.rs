@@ -0,0 +1,195 @@
+//! Handling of watching the project for changes.
+//! On every change of the sources, headers, config
+//! or scripts the chosen action is run again and a
+//! program that is still running gets restarted.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::{Child, Command},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use colored::Colorize;
+
+use crate::util::{self, files::FileHandler, log};
+
+use super::{
+    config::Config,
+    executor::{self, Target},
+};
+
+/// How often the watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub enum WatchAction {
+    Build,
+    Test,
+    Run,
+    /// A custom shell command
+    Exec(String),
+}
+
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+pub struct Watcher<'w> {
+    proj_dir: &'w PathBuf,
+    action: WatchAction,
+    debounce: Duration,
+    /// Scripts of the project, updated whenever the config is parsed
+    scripts: Vec<PathBuf>,
+    /// Program started by the last run of the action
+    child: Option<Child>,
+}
+
+impl<'w> Watcher<'w> {
+    pub fn new(proj_dir: &'w PathBuf, action: WatchAction, debounce: Duration) -> Self {
+        Self {
+            proj_dir,
+            action,
+            debounce,
+            scripts: Vec::new(),
+            child: None,
+        }
+    }
+
+    pub fn watch(&mut self) -> anyhow::Result<()> {
+        loop {
+            self.stop_child();
+            // Failed builds should not stop the watcher
+            if let Err(err) = self.run_action() {
+                eprintln!("{}: {:#}", "error".red().bold(), err);
+            }
+            // Taken after the action so its scripts are known
+            let snapshot = self.snapshot();
+
+            log::status("Watching", "for changes");
+            self.wait_for_change(&snapshot);
+        }
+    }
+
+    fn run_action(&mut self) -> anyhow::Result<()> {
+        // The config is parsed again since project.lua might have changed
+        let cfg_path = self.proj_dir.join("project.lua");
+        let mut cfg = Config::parse(self.proj_dir, FileHandler::new(&cfg_path)?)?;
+        self.scripts = match &cfg.scripts {
+            Some(sm) => sm.scripts().cloned().collect(),
+            None => Vec::new(),
+        };
+
+        let cmd = match &self.action {
+            WatchAction::Build => {
+                return executor::build_proj(self.proj_dir, &mut cfg, Target::Main, false, false, false)
+            }
+            WatchAction::Run => {
+                executor::build_proj(self.proj_dir, &mut cfg, Target::Main, true, true, false)?;
+                let root_name = util::root_dir_name(self.proj_dir)
+                    .context("Failed to get root name of project")?;
+                Command::new(executor::profile_dir(false).join(root_name))
+            }
+            WatchAction::Test => {
+                let mut cmd = Command::new(executor::build_tests(self.proj_dir, cfg)?);
+                cmd.env("SURTUR_TESTS", "*");
+                cmd
+            }
+            WatchAction::Exec(shell_cmd) => {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg(shell_cmd);
+                cmd
+            }
+        };
+        self.spawn_child(cmd)
+    }
+
+    fn spawn_child(&mut self, mut cmd: Command) -> anyhow::Result<()> {
+        cmd.env("SURTUR_PROJ_DIR", self.proj_dir);
+        log::status("Running", log::shell_command(&cmd));
+        if log::is_dry_run() {
+            return Ok(());
+        }
+        let child = cmd
+            .spawn()
+            .context(format!("Failed to run {}", cmd.get_program().to_string_lossy()))?;
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Kills the program of the last run if it is still running
+    fn stop_child(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            if let Ok(None) = child.try_wait() {
+                log::status("Stopping", format!("process {}", child.id()));
+                let _ = child.kill();
+            }
+            let _ = child.wait();
+        }
+    }
+
+    /// Blocks until the watched files changed and no further
+    /// change happened for the debounce duration
+    fn wait_for_change(&self, snapshot: &Snapshot) {
+        let mut last = snapshot.clone();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = self.snapshot();
+            if current != last {
+                last = current;
+                break;
+            }
+        }
+        let mut stable_for = Duration::ZERO;
+        while stable_for < self.debounce {
+            thread::sleep(POLL_INTERVAL);
+            let current = self.snapshot();
+            if current == last {
+                stable_for += POLL_INTERVAL;
+            } else {
+                last = current;
+                stable_for = Duration::ZERO;
+            }
+        }
+        for path in Self::changed(snapshot, &last) {
+            log::verbose(format!("{} changed", path.display()));
+        }
+    }
+
+    /// Modification times of all watched files
+    fn snapshot(&self) -> Snapshot {
+        let mut snapshot = HashMap::new();
+        let paths = [
+            self.proj_dir.join("src"),
+            self.proj_dir.join("include"),
+            self.proj_dir.join("project.lua"),
+        ];
+        for path in paths.iter().chain(self.scripts.iter()) {
+            Self::collect(path, &mut snapshot);
+        }
+        snapshot
+    }
+
+    fn collect(path: &Path, snapshot: &mut Snapshot) {
+        let Ok(metadata) = fs::metadata(path) else {
+            return;
+        };
+        if metadata.is_dir() {
+            for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                Self::collect(&entry.path(), snapshot);
+            }
+        } else if let Ok(modified) = metadata.modified() {
+            snapshot.insert(path.to_path_buf(), modified);
+        }
+    }
+
+    fn changed<'s>(old: &'s Snapshot, new: &'s Snapshot) -> Vec<&'s PathBuf> {
+        let mut changed: Vec<&PathBuf> = new
+            .iter()
+            .filter(|(path, time)| old.get(*path) != Some(*time))
+            .map(|(path, _)| path)
+            .collect();
+        changed.extend(old.keys().filter(|path| !new.contains_key(*path)));
+        changed
+    }
+}
@@ -0,0 +1,134 @@
+//! Handling of workspaces. A workspace is a directory
+//! with a workspace.lua file that lists multiple surtur
+//! projects (members) which share one build directory
+//! and their dependencies.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use mlua::{Lua, Table};
+
+use crate::util::{self, files::FileHandler, CONFIG_FILE, DEFAULT_TARGET_DIR};
+
+use super::{config::Config, deps::DepManager};
+
+pub const WORKSPACE_FILE: &str = "workspace.lua";
+
+pub struct Workspace {
+    pub root_dir: PathBuf,
+    pub members: Vec<Member>,
+    /// Build directory shared by all members
+    pub target_dir: PathBuf,
+}
+
+pub struct Member {
+    pub dir: PathBuf,
+    pub cfg: Config,
+}
+
+impl Member {
+    /// Name of the member's directory, which is also
+    /// the name of its executable or library
+    pub fn dir_name(&self) -> anyhow::Result<&str> {
+        util::root_dir_name(&self.dir).context("Failed to get root name of workspace member")
+    }
+}
+
+/// The workspace.lua of a workspace, without the configs of its members
+pub struct WorkspaceManifest {
+    pub root_dir: PathBuf,
+    /// Members as they are listed, with their directories
+    pub members: Vec<(String, PathBuf)>,
+    /// Build directory shared by all members
+    pub target_dir: PathBuf,
+}
+
+impl WorkspaceManifest {
+    pub fn parse(root_dir: &Path, file: FileHandler) -> anyhow::Result<Self> {
+        let lua = Lua::new();
+
+        lua.load(&file.file_content)
+            .exec()
+            .context("Failed to load workspace.lua")?;
+
+        let members_table: Table = lua
+            .globals()
+            .get("Members")
+            .context("Failed to get members of the workspace even though they are required")?;
+
+        let target_dir = match lua.globals().get::<Option<String>>("TargetDir")? {
+            Some(dir) => root_dir.join(dir),
+            None => root_dir.join(DEFAULT_TARGET_DIR),
+        };
+
+        let mut members = Vec::new();
+
+        for member in members_table.sequence_values::<String>() {
+            let member = member.context("Failed to get workspace member")?;
+            let dir = root_dir.join(&member);
+            members.push((member, dir));
+        }
+
+        Ok(Self {
+            root_dir: root_dir.to_path_buf(),
+            members,
+            target_dir,
+        })
+    }
+
+    /// Whether the project in the directory is a member of the workspace
+    pub fn has_member(&self, proj_dir: &Path) -> bool {
+        let Ok(proj_dir) = proj_dir.canonicalize() else {
+            return false;
+        };
+        self.members
+            .iter()
+            .any(|(_, dir)| dir.canonicalize().is_ok_and(|dir| dir == proj_dir))
+    }
+
+    /// Parses the configs of all members, with additional features enabled
+    pub fn load(&self, features: &[String]) -> anyhow::Result<Workspace> {
+        let mut members = Vec::new();
+        for (member, dir) in &self.members {
+            let cfg_path = dir.join(CONFIG_FILE);
+            let fh = FileHandler::new(&cfg_path)
+                .context(format!("Failed to find config of workspace member `{}`", member))?;
+            let mut cfg = Config::parse(dir, fh)?;
+            cfg.features.extend(features.iter().cloned());
+            members.push(Member { dir: dir.clone(), cfg });
+        }
+
+        Ok(Workspace {
+            root_dir: self.root_dir.clone(),
+            members,
+            target_dir: self.target_dir.clone(),
+        })
+    }
+}
+
+impl Workspace {
+    /// Takes a member out of the workspace by its
+    /// project name or directory name
+    pub fn into_member(self, name: &str) -> anyhow::Result<Member> {
+        for member in self.members {
+            if member.cfg.name == name || member.dir_name()? == name {
+                return Ok(member);
+            }
+        }
+        bail!("Failed to find workspace member `{}`", name)
+    }
+
+    /// Dependencies of all members, members that depend on
+    /// the same dependency only need it once
+    pub fn deps(&self) -> DepManager {
+        let deps: HashSet<_> = self
+            .members
+            .iter()
+            .flat_map(|member| member.cfg.deps.deps.iter().cloned())
+            .collect();
+        DepManager::new(deps)
+    }
+}
@@ -0,0 +1,134 @@
+//! Minimal glob patterns for selecting files in the config.
+//! Supported are `*` (anything except `/`), `?` (a single
+//! character) and `**` (any number of directories).
+//! A pattern that matches a directory matches everything in it.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Checks if the relative path (using `/` as separator) matches the pattern
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match_parts(&pattern, &path)
+}
+
+fn match_parts(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        // Everything inside of a matched directory matches as well
+        (None, _) => true,
+        (Some(&"**"), _) => {
+            match_parts(&pattern[1..], path) || (!path.is_empty() && match_parts(pattern, &path[1..]))
+        }
+        (Some(_), None) => false,
+        (Some(part), Some(name)) => {
+            match_name(part.as_bytes(), name.as_bytes()) && match_parts(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+fn match_name(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            match_name(&pattern[1..], name) || (!name.is_empty() && match_name(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => match_name(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => match_name(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Relative path of the file with `/` as separator
+pub fn relative(path: &Path, base: &Path) -> Option<String> {
+    let rel = path.strip_prefix(base).ok()?;
+    Some(
+        rel.components()
+            .map(|comp| comp.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Finds all files below the base directory that match the pattern
+pub fn find(base: &Path, pattern: &str) -> Vec<PathBuf> {
+    // Only the directory before the first wildcard has to be searched
+    let prefix: Vec<&str> = pattern
+        .split('/')
+        .take_while(|part| !is_glob(part))
+        .collect();
+    let start = base.join(prefix.join("/"));
+    let mut files = Vec::new();
+    if start.is_file() {
+        files.push(start);
+    } else {
+        walk(&start, &mut files);
+        files.retain(|file| relative(file, base).is_some_and(|rel| matches(pattern, &rel)));
+    }
+    files
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_wildcards() {
+        assert!(is_glob("src/*.c"));
+        assert!(is_glob("lib?.c"));
+        assert!(!is_glob("src/main.c"));
+    }
+
+    #[test]
+    fn star_stays_within_a_directory() {
+        assert!(matches("src/*.c", "src/main.c"));
+        assert!(!matches("src/*.c", "src/a/util.c"));
+        assert!(!matches("src/*.c", "src/main.h"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(matches("lib?.c", "liba.c"));
+        assert!(!matches("lib?.c", "lib.c"));
+        assert!(!matches("lib?.c", "libab.c"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_directories() {
+        assert!(matches("src/**/*.c", "src/main.c"));
+        assert!(matches("src/**/*.c", "src/a/b/util.c"));
+        assert!(matches("**/util.c", "util.c"));
+        assert!(!matches("src/**/*.c", "tests/main.c"));
+    }
+
+    #[test]
+    fn matched_directories_match_everything_inside() {
+        assert!(matches("src/platform", "src/platform/linux/io.c"));
+        assert!(matches("src/plat*", "src/platform/io.c"));
+        assert!(!matches("src/platform/io.c", "src/platform"));
+    }
+
+    #[test]
+    fn relative_paths_use_slashes() {
+        let base = Path::new("/proj");
+        assert_eq!(relative(Path::new("/proj/src/a/util.c"), base).as_deref(), Some("src/a/util.c"));
+        assert_eq!(relative(Path::new("/other/util.c"), base), None);
+    }
+}
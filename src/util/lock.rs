@@ -0,0 +1,95 @@
+//! Advisory file locks that keep multiple surtur
+//! processes from using the same directory at once,
+//! e.g. the build directory or the global dependency store.
+
+use std::{
+    env,
+    fs::{self, File, TryLockError},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+use super::log;
+
+pub const LOCK_FILE: &str = ".surtur-lock";
+
+/// Env var for how many seconds to wait for a lock
+pub const LOCK_TIMEOUT: &str = "SURTUR_LOCK_TIMEOUT";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Lock on a directory, it is released when dropped
+pub struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    /// Locks the directory for this process only
+    pub fn exclusive(dir: &Path, what: &str) -> anyhow::Result<Self> {
+        Self::acquire(dir, what, false)
+    }
+
+    /// Locks the directory so other processes can read, but not change it
+    pub fn shared(dir: &Path, what: &str) -> anyhow::Result<Self> {
+        Self::acquire(dir, what, true)
+    }
+
+    fn acquire(dir: &Path, what: &str, shared: bool) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(LOCK_FILE);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context(format!("Failed to open lock file {}", path.display()))?;
+
+        let timeout = Self::timeout()?;
+        let start = Instant::now();
+        let mut waiting = false;
+
+        loop {
+            let res = if shared {
+                file.try_lock_shared()
+            } else {
+                file.try_lock()
+            };
+            match res {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) => {
+                    if !waiting {
+                        log::status("Blocking", format!("waiting for lock on {}", what));
+                        waiting = true;
+                    }
+                    if start.elapsed() >= timeout {
+                        bail!(
+                            "Timed out after {}s waiting for the lock on {} ({})",
+                            timeout.as_secs(),
+                            what,
+                            path.display()
+                        );
+                    }
+                    thread::sleep(RETRY_INTERVAL);
+                }
+                Err(TryLockError::Error(err)) => {
+                    return Err(err).context(format!("Failed to lock {}", path.display()))
+                }
+            }
+        }
+    }
+
+    fn timeout() -> anyhow::Result<Duration> {
+        match env::var(LOCK_TIMEOUT) {
+            Ok(secs) => Ok(Duration::from_secs(secs.parse().context(format!(
+                "`{}` is not a valid value for {}, it has to be a number of seconds",
+                secs, LOCK_TIMEOUT
+            ))?)),
+            Err(_) => Ok(DEFAULT_TIMEOUT),
+        }
+    }
+}
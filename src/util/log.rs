@@ -0,0 +1,91 @@
+//! Output of surtur itself. Everything surtur reports
+//! about what it is doing goes through here, so the
+//! verbosity flags and dry runs apply everywhere.
+
+use std::{
+    ffi::OsStr,
+    fmt::Display,
+    process::Command,
+    sync::atomic::{AtomicBool, AtomicI8, Ordering},
+};
+
+use colored::Colorize;
+
+/// -1 is quiet, 0 is normal, 1 and 2 are -v and -vv
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+pub fn init(verbosity: i8, dry_run: bool) {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn verbosity() -> i8 {
+    VERBOSITY.load(Ordering::Relaxed)
+}
+
+#[inline(always)]
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Reports a step of the build, e.g. `Compiling main.c`
+pub fn status(action: &str, msg: impl Display) {
+    if verbosity() >= 0 {
+        println!("{:>12} {}", action.green().bold(), msg);
+    }
+}
+
+pub fn warn(msg: impl Display) {
+    if verbosity() >= 0 {
+        eprintln!("{}: {}", "warning".yellow().bold(), msg);
+    }
+}
+
+/// Additional information that is shown with -v
+pub fn verbose(msg: impl Display) {
+    if verbosity() >= 1 {
+        println!("{:>12} {}", "Info".cyan().bold(), msg);
+    }
+}
+
+/// Details about surtur's decisions that are shown with -vv
+pub fn debug(msg: impl Display) {
+    if verbosity() >= 2 {
+        println!("{:>12} {}", "Debug".dimmed().bold(), msg.to_string().dimmed());
+    }
+}
+
+/// Prints the command as it would be typed into a shell.
+/// Commands are always shown in dry runs
+pub fn command(cmd: &Command) {
+    if verbosity() >= 1 || is_dry_run() {
+        let label = if is_dry_run() { "Would run" } else { "Running" };
+        println!("{:>12} {}", label.cyan().bold(), shell_command(cmd));
+    }
+}
+
+pub fn shell_command(cmd: &Command) -> String {
+    let mut line = shell_quote(cmd.get_program());
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&shell_quote(arg));
+    }
+    line
+}
+
+/// Quotes the argument if a shell would not take it as is
+pub fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let is_safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+    if is_safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
@@ -28,3 +28,56 @@ impl LuaFunctionInfo {
         }
     }
 }
+
+/// Quoted lua string literal of the text, for writing lua files
+pub fn lua_string(text: &str) -> String {
+    let mut lit = String::with_capacity(text.len() + 2);
+    lit.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => lit.push_str("\\\""),
+            '\\' => lit.push_str("\\\\"),
+            '\n' => lit.push_str("\\n"),
+            '\r' => lit.push_str("\\r"),
+            '\t' => lit.push_str("\\t"),
+            // Decimal escapes have three digits, so digits after them aren't read as part of them
+            c if c.is_ascii_control() => lit.push_str(&format!("\\{:03}", c as u32)),
+            c => lit.push(c),
+        }
+    }
+    lit.push('"');
+    lit
+}
+
+#[cfg(test)]
+mod tests {
+    use mlua::Lua;
+
+    use super::*;
+
+    fn eval(lit: &str) -> String {
+        Lua::new().load(format!("return {}", lit)).eval().unwrap()
+    }
+
+    #[test]
+    fn escapes_quotes_and_backslashes() {
+        let text = r#"say "hi" \ C:\dir"#;
+        assert_eq!(lua_string(text), r#""say \"hi\" \\ C:\\dir""#);
+        assert_eq!(eval(&lua_string(text)), text);
+    }
+
+    #[test]
+    fn escapes_line_breaks_and_control_characters() {
+        let text = "one\ntwo\r\tthree\0";
+        assert_eq!(lua_string(text), r#""one\ntwo\r\tthree\000""#);
+        assert_eq!(eval(&lua_string(text)), text);
+        assert_eq!(eval(&lua_string("\x0123")), "\x0123");
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        let text = "Grüße 日本 🦀 'single'";
+        assert_eq!(lua_string(text), format!("\"{}\"", text));
+        assert_eq!(eval(&lua_string(text)), text);
+    }
+}
@@ -1,21 +1,58 @@
 pub mod files;
+pub mod glob;
+pub mod lock;
+pub mod log;
 pub mod lua_utils;
 /// Provides various utility functions
 pub mod macros;
 
 use std::collections::HashSet;
 use std::env;
+use std::io::{self, Write};
 use std::path::Path;
-use std::process::{exit, Command};
+use std::process::{exit, Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use std::{error::Error, fs, path::PathBuf};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use colored::Colorize;
 
-pub const MISSING_CFG: &str = "Failed to find the project's config file (project.lua)";
+pub const MISSING_CFG: &str =
+    "Failed to find the project's config file (project.lua) in this directory or any parent directory";
+
+pub const CONFIG_FILE: &str = "project.lua";
+
+/// Env var for the directory all build outputs go to
+pub const TARGET_DIR: &str = "SURTUR_TARGET_DIR";
+
+pub const DEFAULT_TARGET_DIR: &str = "build";
+
+/// Env var for the lockfile of the project or workspace
+pub const LOCKFILE: &str = "SURTUR_LOCKFILE";
+
+pub const LOCKFILE_NAME: &str = "surtur.lock";
+
+pub const MISSING_WORKSPACE: &str = "Failed to find the workspace's config file (workspace.lua)";
 
 pub const DEFAULT_COMPILER: &str = "gcc";
 
+/// Directory all build outputs go to. This is set by
+/// the cli, but can be overwritten with SURTUR_TARGET_DIR
+pub fn target_dir() -> PathBuf {
+    env::var_os(TARGET_DIR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_TARGET_DIR))
+}
+
+/// Lockfile that pins the versions of the dependencies. This is set by the cli
+pub fn lockfile() -> PathBuf {
+    env::var_os(LOCKFILE)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(LOCKFILE_NAME))
+}
+
 #[inline(always)]
 pub fn root_dir_name(cur_dir: &Path) -> Option<&str> {
     cur_dir.file_name()?.to_str()
@@ -27,17 +64,30 @@ pub fn create_dir(dir: &str) -> anyhow::Result<()> {
 }
 
 pub fn run_c_program(cmd: &mut Command, cur_dir: &PathBuf) -> anyhow::Result<()> {
+    let code = wait_c_program(cmd, cur_dir)?;
+    exit(code)
+}
+
+/// Runs the c program and returns its exit code instead of exiting
+pub fn wait_c_program(cmd: &mut Command, cur_dir: &PathBuf) -> anyhow::Result<i32> {
     env::set_var("SURTUR_PROJ_DIR", cur_dir);
 
+    log::debug(format!("SURTUR_PROJ_DIR={}", cur_dir.display()));
+    if log::is_dry_run() {
+        log::command(cmd);
+        return Ok(0);
+    }
+    log::status("Running", log::shell_command(cmd));
+
     match cmd.status() {
         Ok(status) => {
             let code = status.code();
             match code {
-                Some(c) => exit(c),
+                Some(c) => Ok(c),
                 None => {
                     println!("{}", "Program exited by throwing an error".red());
                     println!("{}: ... xDDDD did you seriously think C would show you the error? Pathetic.", "Error".red());
-                    exit(1);
+                    Ok(1)
                 }
             }
         }
@@ -47,21 +97,92 @@ pub fn run_c_program(cmd: &mut Command, cur_dir: &PathBuf) -> anyhow::Result<()>
     }
 }
 
+/// Runs a command of the build (compiler, linker, ...) and fails
+/// if it does not succeed. Nothing is run during dry runs
+pub fn exec(cmd: &mut Command) -> anyhow::Result<()> {
+    log::command(cmd);
+    if log::is_dry_run() {
+        return Ok(());
+    }
+    let program = cmd.get_program().to_string_lossy().to_string();
+    let status = cmd
+        .status()
+        .context(format!("Failed to run `{}`", program))?;
+    if !status.success() {
+        bail!("`{}` failed with {}", program, status);
+    }
+    Ok(())
+}
+
+/// Number of commands that are run at the same time
+pub fn jobs() -> usize {
+    thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// Runs the commands in parallel and returns how many of them failed.
+/// The output of every command is printed at once when it finishes,
+/// so diagnostics of different commands don't get mixed up
+pub fn exec_parallel(cmds: Vec<Command>) -> anyhow::Result<usize> {
+    let failed = AtomicUsize::new(0);
+    exec_parallel_with(cmds, |_, output| {
+        let _ = io::stdout().write_all(&output.stdout);
+        let _ = io::stderr().write_all(&output.stderr);
+        if !output.status.success() {
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+    })?;
+    Ok(failed.into_inner())
+}
+
+/// Runs the commands in parallel and hands the index and output
+/// of every command to the callback when it finishes
+pub fn exec_parallel_with<F>(cmds: Vec<Command>, on_output: F) -> anyhow::Result<()>
+where
+    F: Fn(usize, Output) + Sync,
+{
+    for cmd in &cmds {
+        log::command(cmd);
+    }
+    if log::is_dry_run() {
+        return Ok(());
+    }
+    let queue = Mutex::new(cmds.into_iter().enumerate());
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs())
+            .map(|_| {
+                scope.spawn(|| -> anyhow::Result<()> {
+                    loop {
+                        let Some((i, mut cmd)) = queue.lock().unwrap().next() else {
+                            return Ok(());
+                        };
+                        let program = cmd.get_program().to_string_lossy().to_string();
+                        let output = cmd.output().context(format!("Failed to run `{}`", program))?;
+                        on_output(i, output);
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().expect("Worker thread panicked"))
+    })
+}
+
 // recursively go through directory
 // TODO: Remove recursion as it creates a bunch of unnessecary heap allocations
-fn get_files(path: &PathBuf, ending: &str) -> HashSet<PathBuf> {
+fn get_files(path: &PathBuf, extensions: &[&str]) -> HashSet<PathBuf> {
     let dir = fs::read_dir(path)
         .unwrap_or_else(|_| panic!("Failed to find directory: {}", path.display()));
     dir.flatten()
         .flat_map(|entry| {
             let file_type = entry.file_type().expect("Failed to get file type");
             if file_type.is_dir() {
-                get_files(&entry.path(), ending)
+                get_files(&entry.path(), extensions)
             } else {
-                let file_name = entry.file_name().to_string_lossy().to_string();
-                let file_ending = &file_name[file_name.len() - 2..];
-                if file_ending == ending {
-                    HashSet::from([entry.path()])
+                let path = entry.path();
+                let extension = path.extension().map(|ext| ext.to_string_lossy().to_string());
+                if extension.is_some_and(|ext| extensions.contains(&ext.as_str())) {
+                    HashSet::from([path])
                 } else {
                     HashSet::new()
                 }
@@ -72,17 +193,22 @@ fn get_files(path: &PathBuf, ending: &str) -> HashSet<PathBuf> {
 
 #[inline(always)]
 pub fn get_header_files(path: &PathBuf) -> HashSet<PathBuf> {
-    get_files(path, ".h")
+    get_files(path, &["h"])
 }
 
+pub const SRC_EXTENSIONS: [&str; 3] = ["c", "s", "S"];
+
+pub fn is_src_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| SRC_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+}
+
+/// C and assembly (.s, .S) source files
 #[inline(always)]
 pub fn get_src_files(path: &PathBuf) -> HashSet<PathBuf> {
-    get_files(path, ".c")
+    get_files(path, &SRC_EXTENSIONS)
 }
 
 pub fn result_to_option<T, E: Error>(res: Result<T, E>) -> Option<T> {
-    match res {
-        Ok(val) => Some(val),
-        Err(_) => None,
-    }
+    res.ok()
 }